@@ -7,3 +7,8 @@ cargo build --release
         [pscustomobject]@{ Day = $_; Milliseconds = $t };
     }
 } | Sort-Object -Property Day
+
+# Day 7 can search forwards or backwards; compare them on the same input.
+if (Test-Path -Path "target/release/day07.exe") {
+    & "target/release/day07.exe" compare
+}
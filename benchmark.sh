@@ -1,3 +1,5 @@
 #!/usr/bin/bash
 
 cargo build --release && for i in {01..25}; do [ -e "target/release/day$i" ] && time "target/release/day$i"; done
+# Day 7 can search forwards or backwards; compare them on the same input.
+[ -e "target/release/day07" ] && target/release/day07 compare
@@ -1,7 +1,16 @@
-use std::fs;
+use std::{env, fs, fs::File, io::{self, ErrorKind, Read}};
 use regex::Regex;
 
+const CHUNK_SIZE: usize = 64 * 1024;
+
 fn main() {
+    // Pass a path to stream a memory dump that is too big to read into a String.
+    if let Some(path) = env::args().nth(1) {
+        let (p1, p2) = scan(File::open(path).unwrap(), CHUNK_SIZE).unwrap();
+        println!("Part 1: {}", p1);
+        println!("Part 2: {}", p2);
+        return
+    }
     let puzzle = fs::read_to_string("puzzles/day03.txt").unwrap();
     println!("Part 1: {}", part1(&puzzle));
     println!("Part 2: {}", part2(&puzzle));
@@ -33,6 +42,108 @@ fn part2(input: &str) -> i32 {
     }).map(|(x,y)| x * y).sum()
 }
 
+const MUL: &[u8] = b"mul(";
+const DO: &[u8] = b"do()";
+const DONT: &[u8] = b"don't()";
+
+// Where we are inside a partially-read instruction. This is all the state we
+// need to carry from one chunk to the next.
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Start,
+    Literal(&'static [u8], usize), // matched this many bytes of the literal
+    X(Option<i64>),
+    Y(i64, Option<i64>),
+}
+
+// A byte-at-a-time version of the part 2 regex. None of the bytes inside an
+// instruction can start another instruction, so when a match fails we only
+// need to retry the byte that broke it.
+struct Scanner {
+    state: State,
+    enabled: bool,
+    sum1: i128, // a long enough dump could overflow an i64 even if every product fits
+    sum2: i128,
+}
+
+impl Scanner {
+    fn new() -> Scanner {
+        Scanner { state: State::Start, enabled: true, sum1: 0, sum2: 0 }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if !self.step(b) {
+                self.state = State::Start;
+                self.step(b);
+            }
+        }
+    }
+
+    // Returns false if this byte can't continue the current instruction.
+    fn step(&mut self, b: u8) -> bool {
+        self.state = match (self.state, b) {
+            (State::Start, b'm') => State::Literal(MUL, 1),
+            (State::Start, b'd') => State::Literal(DO, 1),
+            (State::Start, _) => State::Start,
+            (State::Literal(lit, 2), b'n') if lit == DO => State::Literal(DONT, 3),
+            (State::Literal(lit, n), b) if lit[n] == b => {
+                if n + 1 < lit.len() {
+                    State::Literal(lit, n + 1)
+                } else if lit == MUL {
+                    State::X(None)
+                } else {
+                    self.enabled = lit == DO;
+                    State::Start
+                }
+            },
+            (State::X(x), b'0'..=b'9') => match push_digit(x, b) {
+                Some(x) => State::X(Some(x)),
+                None => return false,
+            },
+            (State::X(Some(x)), b',') => State::Y(x, None),
+            (State::Y(x, y), b'0'..=b'9') => match push_digit(y, b) {
+                Some(y) => State::Y(x, Some(y)),
+                None => return false,
+            },
+            // Like the operands, a product too big for an i64 isn't a valid instruction.
+            (State::Y(x, Some(y)), b')') => match x.checked_mul(y) {
+                Some(product) => {
+                    self.sum1 += product as i128;
+                    if self.enabled {
+                        self.sum2 += product as i128;
+                    }
+                    State::Start
+                },
+                None => return false,
+            },
+            _ => return false,
+        };
+        true
+    }
+}
+
+// Numbers too big for an i64 can't be valid instructions anyway.
+fn push_digit(n: Option<i64>, digit: u8) -> Option<i64> {
+    n.unwrap_or(0).checked_mul(10)?.checked_add((digit - b'0') as i64)
+}
+
+// Reads the input a chunk at a time, so memory use doesn't depend on the input size.
+// Returns the sums for both parts.
+fn scan<R: Read>(mut reader: R, chunk_size: usize) -> io::Result<(i128, i128)> {
+    let mut scanner = Scanner::new();
+    let mut buffer = vec![0; chunk_size];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => scanner.feed(&buffer[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((scanner.sum1, scanner.sum2))
+}
+
 #[cfg(test)]
 mod day03 {
     use super::*;
@@ -48,5 +159,29 @@ mod day03 {
     #[test]
     fn test2() {
         assert_eq!(part2(SAMPLE2), 48)
-    }   
+    }
+
+    #[test]
+    fn streaming() {
+        // Every chunk size splits some instruction across a boundary.
+        for chunk_size in 1..=SAMPLE2.len() {
+            assert_eq!(scan(SAMPLE1.as_bytes(), chunk_size).unwrap().0, 161);
+            assert_eq!(scan(SAMPLE2.as_bytes(), chunk_size).unwrap(), (161, 48));
+        }
+    }
+
+    #[test]
+    fn streaming_restarts() {
+        let input = "mumul(1,2)ddo()mul(3,mul(4,5)don'don't()mul(6,7)mul(99999999999999999999,1)";
+        assert_eq!(scan(input.as_bytes(), 3).unwrap(), (64, 22));
+    }
+
+    #[test]
+    fn big_products() {
+        let input = "mul(9999999999,9999999999)mul(3037000499,3037000499)mul(3037000500,3037000500)";
+        // Only the middle product fits in an i64, and two of them would overflow an i64 sum.
+        assert_eq!(scan(input.as_bytes(), 7).unwrap().0, 9223372030926249001);
+        let twice = "mul(3037000499,3037000499)".repeat(2);
+        assert_eq!(scan(twice.as_bytes(), 7).unwrap().0, 2 * 9223372030926249001);
+    }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
-use std::fs;
+use std::{collections::HashMap, fs};
+use aho_corasick::AhoCorasick;
 use itertools::Itertools;
 
 fn main() {
@@ -9,74 +10,269 @@ fn main() {
 
 // This problem looked like it would be easy, but Rust doesn't support
 // lookaheads in its regexes, and searching diagonally isn't obvious either.
-fn part1(input: &str) -> i32 {
-    let m: Vec<Vec<char>> = input.lines().map(|line| {
-        line.chars().collect()      
-    }).collect();
+fn part1(input: &str) -> usize {
+    find_words(&parse(input), &["XMAS"], false).len()
+}
+
+fn part2(input: &str) -> usize {
+    let x_mas = Template::parse("M.S\n.A.\nM.S", '.');
+    find_template(&parse(input), &x_mas, false).len()
+}
+
+fn parse(input: &str) -> Vec<Vec<char>> {
+    input.lines().map(|line| {
+        line.chars().collect()
+    }).collect()
+}
 
-    let mut count = 0;
-    let rows = m.len() as i32;
-    let cols = m[0].len() as i32;
+const DIRECTIONS: [(i32, i32); 8] = [(-1,-1), (-1,0), (-1,1), (0,-1), (0,1), (1,-1), (1,0), (1,1)];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct WordMatch<'a> {
+    word: &'a str,
+    cells: Vec<(usize, usize)>,
+    direction: (i32, i32),
+}
+
+// Finds every word starting at every cell in all 8 directions. With wrap,
+// words can run off one edge of the grid and continue on the opposite edge.
+fn find_words<'a>(haystack: &[Vec<char>], words: &[&'a str], wrap: bool) -> Vec<WordMatch<'a>> {
+    if wrap {
+        probe_words(haystack, words, true)
+    } else {
+        scan_words(haystack, words)
+    }
+}
 
+// Probing every direction from every cell costs a pass per word. This is
+// still how we handle wrap-around, where lines have no natural start or end.
+fn probe_words<'a>(haystack: &[Vec<char>], words: &[&'a str], wrap: bool) -> Vec<WordMatch<'a>> {
+    let rows = haystack.len() as i32;
+    let cols = haystack.first().map_or(0, |row| row.len()) as i32;
+    let mut matches = vec![];
     for (row,col) in (0..rows).cartesian_product(0..cols) {
-        count += explore(&m, row, col);
-    }
-    count
-}
-
-fn part2(input: &str) -> i32 {
-    let m: Vec<Vec<char>> = input.lines().map(|line| {
-        line.chars().collect()      
-    }).collect();
-    let rows = m.len();
-    let cols = m[0].len();
-    let mut count = 0;
-    for (row, col) in (1..rows-1).cartesian_product(1..cols-1) {
-        // Bounds checking shouldn't be necessary now due to offsets.
-        if m[row][col] == 'A' {
-            let d1 = String::from_iter([m[row-1][col-1], 'A', m[row+1][col+1]]);
-            let d2 = String::from_iter([m[row+1][col-1], 'A', m[row-1][col+1]]);
-            if (d1 == "MAS" || d1 == "SAM") && (d2 == "MAS" || d2 == "SAM") {
-                count += 1
-            }
+        for word in words {
+            matches.extend(explore(haystack, word, row, col, wrap));
         }
-    };
-    count
+    }
+    matches
 }
 
-fn explore(haystack: &[Vec<char>], row: i32, col: i32) -> i32 {
-    let mut count = 0;
-    for dr in -1..=1 {
-        for dc in -1..=1 {
-            if dr == 0 && dc == 0 {
-                continue
-            }
-            if search1(haystack, row, col, dr, dc).is_some() {
-                count += 1
+// A row, column or diagonal of the grid, read in the given direction.
+struct Line {
+    text: String,
+    cells: Vec<(usize, usize)>,
+    char_at_byte: Vec<usize>,
+    direction: (i32, i32),
+}
+
+impl Line {
+    fn new(haystack: &[Vec<char>], cells: Vec<(usize, usize)>, direction: (i32, i32)) -> Line {
+        let mut text = String::new();
+        let mut char_at_byte = vec![];
+        for (i, &(r, c)) in cells.iter().enumerate() {
+            let letter = haystack[r][c];
+            text.push(letter);
+            char_at_byte.extend(std::iter::repeat_n(i, letter.len_utf8()));
+        }
+        Line { text, cells, char_at_byte, direction }
+    }
+}
+
+// Every row, column, diagonal and anti-diagonal, each read in one direction only.
+// The opposite directions are handled by also searching for the reversed words.
+fn lines(haystack: &[Vec<char>]) -> Vec<Line> {
+    let rows = haystack.len() as i32;
+    let cols = haystack.first().map_or(0, |row| row.len()) as i32;
+    let mut starts = vec![];
+    for r in 0..rows {
+        starts.push((r, 0, (0, 1)));
+        starts.push((r, 0, (1, 1)));
+        starts.push((r, cols - 1, (1, -1)));
+    }
+    for c in 0..cols {
+        starts.push((0, c, (1, 0)));
+        if c > 0 {
+            starts.push((0, c, (1, 1)));
+        }
+        if c < cols - 1 {
+            starts.push((0, c, (1, -1)));
+        }
+    }
+    starts.into_iter().map(|(mut r, mut c, (dr, dc))| {
+        let mut cells = vec![];
+        while 0 <= r && r < rows && 0 <= c && c < cols {
+            cells.push((r as usize, c as usize));
+            r += dr;
+            c += dc;
+        }
+        Line::new(haystack, cells, (dr, dc))
+    }).collect()
+}
+
+// Scans each line once with an Aho-Corasick automaton over all the words and their
+// reversals, so the cost is about linear in the size of the grid no matter how
+// many words we look for (plus the number of matches, of course).
+fn scan_words<'a>(haystack: &[Vec<char>], words: &[&'a str]) -> Vec<WordMatch<'a>> {
+    // Palindromes and repeated words share a pattern, so keep every (word, reversed) per pattern.
+    let mut patterns: Vec<String> = vec![];
+    let mut owners: Vec<Vec<(&'a str, bool)>> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for &word in words.iter().filter(|word| !word.is_empty()) {
+        for (pattern, reversed) in [(word.to_string(), false), (word.chars().rev().collect(), true)] {
+            let i = *index.entry(pattern.clone()).or_insert_with(|| {
+                patterns.push(pattern);
+                owners.push(vec![]);
+                patterns.len() - 1
+            });
+            owners[i].push((word, reversed));
+        }
+    }
+    if patterns.is_empty() {
+        return vec![]
+    }
+
+    let automaton = AhoCorasick::new(&patterns).unwrap();
+    let mut matches = vec![];
+    for line in lines(haystack) {
+        for m in automaton.find_overlapping_iter(&line.text) {
+            let first = line.char_at_byte[m.start()];
+            let last = line.char_at_byte[m.end() - 1];
+            let span = &line.cells[first..=last];
+            for &(word, reversed) in owners[m.pattern().as_usize()].iter() {
+                let (dr, dc) = line.direction;
+                let (cells, direction) = if reversed {
+                    (span.iter().rev().copied().collect(), (-dr, -dc))
+                } else {
+                    (span.to_vec(), (dr, dc))
+                };
+                matches.push(WordMatch { word, cells, direction });
             }
         }
     }
-    count
+    matches
+}
+
+fn explore<'a>(haystack: &[Vec<char>], word: &'a str, row: i32, col: i32, wrap: bool) -> Vec<WordMatch<'a>> {
+    let word_chars: Vec<char> = word.chars().collect();
+    DIRECTIONS.iter().filter_map(|&(dr, dc)| {
+        search(haystack, &word_chars, row, col, dr, dc, wrap).map(|cells| WordMatch {
+            word,
+            cells,
+            direction: (dr, dc),
+        })
+    }).collect()
 }
 
-fn search1(haystack: &[Vec<char>], row: i32, col: i32, dr: i32, dc: i32) -> Option<()> {
+fn search(haystack: &[Vec<char>], word: &[char], row: i32, col: i32, dr: i32, dc: i32, wrap: bool) -> Option<Vec<(usize, usize)>> {
     let rows = haystack.len() as i32;
     let cols = haystack[0].len() as i32;
-    for radius in 0..=3 {
-        let r = row + dr * radius;
-        if r < 0 || rows <= r {
-            return None
+    let mut cells = vec![];
+    for (radius, &letter) in word.iter().enumerate() {
+        let mut r = row + dr * radius as i32;
+        let mut c = col + dc * radius as i32;
+        if wrap {
+            r = r.rem_euclid(rows);
+            c = c.rem_euclid(cols);
         }
-        let c = col + dc * radius;
-        if c < 0 || cols <= c {
+        if r < 0 || rows <= r || c < 0 || cols <= c {
             return None
         }
-        let l = haystack[r as usize][c as usize];
-        if l != ['X','M','A','S'][radius as usize] {
+        if haystack[r as usize][c as usize] != letter {
             return None
         }
+        cells.push((r as usize, c as usize));
     }
-    Some(())
+    Some(cells)
+}
+
+// A 2D stencil. Cells that are None match any letter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Template {
+    cells: Vec<Vec<Option<char>>>,
+    rotations: u8, // quarter turns clockwise from the original
+    reflected: bool, // mirrored left-to-right before rotating
+}
+
+impl Template {
+    fn parse(input: &str, wildcard: char) -> Template {
+        let cells = input.lines().map(|line| {
+            line.chars().map(|c| if c == wildcard { None } else { Some(c) }).collect()
+        }).collect();
+        Template { cells, rotations: 0, reflected: false }
+    }
+
+    fn rows(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cols(&self) -> usize {
+        self.cells.first().map_or(0, |row| row.len())
+    }
+
+    fn rotate(&self) -> Template {
+        let cells = (0..self.cols()).map(|c| {
+            (0..self.rows()).rev().map(|r| self.cells[r][c]).collect()
+        }).collect();
+        Template { cells, rotations: (self.rotations + 1) % 4, reflected: self.reflected }
+    }
+
+    fn reflect(&self) -> Template {
+        let cells = self.cells.iter().map(|row| row.iter().rev().copied().collect()).collect();
+        Template { cells, rotations: self.rotations, reflected: !self.reflected }
+    }
+
+    // All distinct rotations and reflections. A symmetric stencil like the
+    // X-MAS cross has fewer than 8, and we must not count its matches twice.
+    fn orientations(&self) -> Vec<Template> {
+        let mut orientations: Vec<Template> = vec![];
+        for start in [self.clone(), self.reflect()] {
+            let mut t = start;
+            for _ in 0..4 {
+                if !orientations.iter().any(|o| o.cells == t.cells) {
+                    orientations.push(t.clone());
+                }
+                t = t.rotate();
+            }
+        }
+        orientations
+    }
+
+    fn matches_at(&self, haystack: &[Vec<char>], row: usize, col: usize, wrap: bool) -> bool {
+        let rows = haystack.len();
+        let cols = haystack[0].len();
+        if !wrap && (row + self.rows() > rows || col + self.cols() > cols) {
+            return false
+        }
+        self.cells.iter().enumerate().all(|(r, line)| {
+            line.iter().enumerate().all(|(c, cell)| match cell {
+                Some(letter) => haystack[(row + r) % rows][(col + c) % cols] == *letter,
+                None => true,
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TemplateMatch {
+    row: usize, // top-left corner of the oriented stencil
+    col: usize,
+    rotations: u8,
+    reflected: bool,
+}
+
+fn find_template(haystack: &[Vec<char>], template: &Template, wrap: bool) -> Vec<TemplateMatch> {
+    let rows = haystack.len();
+    let cols = haystack.first().map_or(0, |row| row.len());
+    let mut matches = vec![];
+    for t in template.orientations() {
+        for (row, col) in (0..rows).cartesian_product(0..cols) {
+            if t.matches_at(haystack, row, col, wrap) {
+                matches.push(TemplateMatch { row, col, rotations: t.rotations, reflected: t.reflected });
+            }
+        }
+    }
+    matches
 }
 
 #[cfg(test)]
@@ -102,5 +298,47 @@ MXMXAXMASX";
     #[test]
     fn test2() {
         assert_eq!(part2(SAMPLE), 9)
-    } 
+    }
+
+    #[test]
+    fn words() {
+        let matches = find_words(&parse("XMAS\nAXXX\nSXXX"), &["SAM", "XMAS", "AM"], false);
+        assert_eq!(matches.len(), 4);
+        assert!(matches.contains(&WordMatch { word: "SAM", cells: vec![(0,3), (0,2), (0,1)], direction: (0,-1) }));
+        assert!(matches.contains(&WordMatch { word: "XMAS", cells: vec![(0,0), (0,1), (0,2), (0,3)], direction: (0,1) }));
+        assert!(matches.contains(&WordMatch { word: "AM", cells: vec![(0,2), (0,1)], direction: (0,-1) }));
+        assert!(matches.contains(&WordMatch { word: "AM", cells: vec![(1,0), (0,1)], direction: (-1,1) }));
+    }
+
+    #[test]
+    fn automaton() {
+        let grid = parse(SAMPLE);
+        let words = ["XMAS", "MAS", "AMA", "M", "XX", "MMMS", "SAMXMS"];
+        let mut scanned = scan_words(&grid, &words);
+        let mut probed = probe_words(&grid, &words, false);
+        scanned.sort();
+        probed.sort();
+        assert_eq!(scanned, probed);
+    }
+
+    #[test]
+    fn wrap() {
+        let grid = parse("ASXM\nXXXX");
+        assert!(find_words(&grid, &["XMAS"], false).is_empty());
+        let matches = find_words(&grid, &["XMAS"], true);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].cells, vec![(0,2), (0,3), (0,0), (0,1)]);
+    }
+
+    #[test]
+    fn orientations() {
+        let x_mas = Template::parse("M.S\n.A.\nM.S", '.');
+        assert_eq!(x_mas.orientations().len(), 4);
+        let el = Template::parse("X.\nXX", '.');
+        assert_eq!(el.orientations().len(), 4);
+        let skew = Template::parse("XX.\n.XX", '.');
+        assert_eq!(skew.orientations().len(), 4);
+        let tetromino = Template::parse("X..\nXXX", '.');
+        assert_eq!(tetromino.orientations().len(), 8);
+    }
 }
\ No newline at end of file
@@ -1,61 +1,241 @@
-use std::cmp::Ordering;
-use std::fs;
-use std::collections::HashMap;
+use std::{fmt, fs};
 
-use regex::Regex;
+use petgraph::{algo::{tarjan_scc, toposort}, graphmap::DiGraphMap};
 
 fn main() {
     let puzzle = fs::read_to_string("puzzles/day05.txt").unwrap();
-    let (p1, p2) = solve(&puzzle);
+    let (rules, updates) = parse(&puzzle);
+    for (i, page) in unruled_pages(&rules, &updates) {
+        eprintln!("Warning: page {} in update {} does not appear in any rule", page, i + 1);
+    }
+    let (p1, p2) = solve(&rules, &updates);
     println!("Part 1: {}", p1);
     println!("Part 2: {}", p2);
 }
 
-fn parse(input: &str) -> (HashMap<(u16,u16), Ordering>,Vec<&str>) {
-    let s: Vec<&str> = input.trim().split("\n\n").collect();
-
-    let re = Regex::new(r"(?P<x>\d{2})\|(?P<y>\d{2})").unwrap();
-    let mut rules = HashMap::new();
-    re.captures_iter(s[0]).for_each(|cap| {
-        let x = &cap["x"];
-        let y = &cap["y"];
-        let x = x.parse().unwrap();
-        let y = y.parse().unwrap();
-        rules.insert((x,y), Ordering::Less);
-        rules.insert((y,x), Ordering::Greater);
-    });
+// Page identifiers are kept as the strings from the input, so they can be
+// any length and don't even have to be numbers.
+type Page<'a> = &'a str;
 
-    let updates: Vec<&str> = s[1].split("\n").collect();
+// An edge X -> Y means page X must come before page Y.
+type Rules<'a> = DiGraphMap<Page<'a>, ()>;
 
+// Rules are any lines with a pipe and updates are any other non-blank lines.
+// We don't care about blank lines, CRLF line endings, or spaces around pages.
+fn parse(input: &str) -> (Rules<'_>, Vec<Vec<Page<'_>>>) {
+    let mut rules = Rules::new();
+    let mut updates = vec![];
+    for line in input.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match line.split_once('|') {
+            Some((x, y)) => {
+                rules.add_edge(x.trim(), y.trim(), ());
+            },
+            None => updates.push(line.split(',').map(str::trim).collect()),
+        }
+    }
     (rules, updates)
 }
 
-fn solve(input: &str) -> (u16,u16) {
-    let (rules, updates) = parse(input);
+// Pages that no rule mentions can't be ordered, so they are probably typos.
+// Returns the index of the update and the page.
+fn unruled_pages<'a>(rules: &Rules<'a>, updates: &[Vec<Page<'a>>]) -> Vec<(usize, Page<'a>)> {
+    updates.iter().enumerate().flat_map(|(i, pages)| {
+        pages.iter().filter(|page| !rules.contains_node(page)).map(move |&page| (i, page))
+    }).collect()
+}
 
+#[derive(Debug, PartialEq, Eq)]
+struct Analysis<'a> {
+    violations: Vec<(Page<'a>,Page<'a>)>, // rules X|Y where Y is printed before X
+    cycles: Vec<Vec<Page<'a>>>, // groups of pages whose rules contradict each other
+    missing: Vec<(Page<'a>,Page<'a>)>, // pairs of pages with no rule between them
+    order: Option<Vec<Page<'a>>>, // a valid order for the update, unless there is a cycle
+}
+
+// Only the rules between pages in this update matter. The full rule set is
+// usually cyclic, so we can't just sort the whole graph once.
+fn analyze<'a>(rules: &Rules<'a>, pages: &[Page<'a>]) -> Analysis<'a> {
+    let mut used = Rules::new();
+    let mut violations = vec![];
+    let mut missing = vec![];
+    for &page in pages {
+        used.add_node(page);
+    }
+    for (i, &a) in pages.iter().enumerate() {
+        for &b in &pages[i+1..] {
+            let forward = rules.contains_edge(a, b);
+            let backward = rules.contains_edge(b, a);
+            if forward {
+                used.add_edge(a, b, ());
+            }
+            if backward {
+                used.add_edge(b, a, ());
+                violations.push((b, a));
+            }
+            if !forward && !backward {
+                missing.push((a, b));
+            }
+        }
+    }
+
+    let mut cycles: Vec<Vec<Page>> = tarjan_scc(&used).into_iter().filter(|scc| scc.len() > 1).map(|mut scc| {
+        scc.sort();
+        scc
+    }).collect();
+    cycles.sort();
+
+    let order = toposort(&used, None).ok();
+
+    Analysis { violations, cycles, missing, order }
+}
+
+// The puzzle answers only make sense for numbered pages, so updates whose
+// middle page isn't a number don't count towards them.
+fn solve(rules: &Rules, updates: &[Vec<Page>]) -> (u64,u64) {
     let mut middle_page_sum1 = 0;
     let mut middle_page_sum2 = 0;
-    for update in updates {
-        let mut pages: Vec<u16> = update.split(",").map(|e| e.parse().unwrap()).collect();
-        let comparator = |&a,&b| *rules.get(&(a,b)).unwrap() == Ordering::Less;
+    for pages in updates {
+        let analysis = analyze(rules, pages);
 
-        if pages.is_sorted_by(comparator) {
+        if analysis.violations.is_empty() {
             let middle = pages[pages.len()/2];
-            middle_page_sum1 += middle;
-        } else {
-            pages.sort_by(|&a,&b| *rules.get(&(a,b)).unwrap());
-            let middle = pages[pages.len()/2];
-            middle_page_sum2 += middle;
+            middle_page_sum1 += middle.parse::<u64>().unwrap_or(0);
+        } else if let Some(repair) = repair(rules, pages) {
+            let fixed = apply(pages, &repair.moves);
+            let middle = fixed[fixed.len()/2];
+            middle_page_sum2 += middle.parse::<u64>().unwrap_or(0);
         }
-        
+        // Otherwise the rules for this update are cyclic and no order can fix it.
     }
     (middle_page_sum1, middle_page_sum2)
 }
 
+#[derive(Debug, PartialEq, Eq)]
+struct Move<'a> {
+    page: Page<'a>,
+    before: Option<Page<'a>>, // None moves the page to the end
+}
+
+impl fmt::Display for Move<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.before {
+            Some(before) => write!(f, "move {} before {}", self.page, before),
+            None => write!(f, "move {} to the end", self.page),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Repair<'a> {
+    keep: Vec<Page<'a>>, // the most pages that can stay where they are
+    moves: Vec<Move<'a>>,
+}
+
+// The fewest moves keep the most pages where they are. We can keep any pages
+// as long as no two of them are the wrong way round, following the rules
+// through other pages in the update too. Being the wrong way round is itself a
+// partial order on the pages, so the most we can keep is its largest antichain,
+// which (by Dilworth and König) we can read off a maximum matching. Returns
+// None if the rules for this update are cyclic.
+fn repair<'a>(rules: &Rules<'a>, pages: &[Page<'a>]) -> Option<Repair<'a>> {
+    let n = pages.len();
+    // before[i][j] if page i has to come before page j.
+    let mut before: Vec<Vec<bool>> = (0..n).map(|i| (0..n).map(|j| rules.contains_edge(pages[i], pages[j])).collect()).collect();
+    for k in 0..n {
+        let through = before[k].clone();
+        for row in before.iter_mut().filter(|row| row[k]) {
+            row.iter_mut().zip(&through).for_each(|(cell, &reach)| *cell |= reach);
+        }
+    }
+    if (0..n).any(|i| before[i][i]) {
+        return None
+    }
+    // The later pages that have to come before each page.
+    let wrong_way: Vec<Vec<usize>> = (0..n).map(|i| (i+1..n).filter(|&j| before[j][i]).collect()).collect();
+
+    // Kuhn's algorithm. matched[j] is the page matched to page j on the right.
+    fn augment(i: usize, edges: &[Vec<usize>], seen: &mut [bool], matched: &mut [Option<usize>]) -> bool {
+        for &j in &edges[i] {
+            if !seen[j] {
+                seen[j] = true;
+                if matched[j].is_none_or(|k| augment(k, edges, seen, matched)) {
+                    matched[j] = Some(i);
+                    return true
+                }
+            }
+        }
+        false
+    }
+    let mut matched = vec![None; n];
+    let unmatched: Vec<usize> = (0..n).filter(|&i| !augment(i, &wrong_way, &mut vec![false; n], &mut matched)).collect();
+
+    // König: follow alternating paths from the unmatched pages on the left.
+    // The antichain is every page reached on the left but not on the right.
+    let (mut left, mut right) = (vec![false; n], vec![false; n]);
+    unmatched.iter().for_each(|&i| left[i] = true);
+    let mut stack = unmatched;
+    while let Some(i) = stack.pop() {
+        for &j in &wrong_way[i] {
+            if !right[j] {
+                right[j] = true;
+                if let Some(k) = matched[j].filter(|&k| !left[k]) {
+                    left[k] = true;
+                    stack.push(k);
+                }
+            }
+        }
+    }
+    let keep: Vec<Page> = (0..n).filter(|&i| left[i] && !right[i]).map(|i| pages[i]).collect();
+
+    // Any order that follows the rules and keeps those pages as they are.
+    let mut constraints = Rules::new();
+    for &page in pages {
+        constraints.add_node(page);
+    }
+    for (i, j) in (0..n).flat_map(|i| (0..n).map(move |j| (i, j))) {
+        if rules.contains_edge(pages[i], pages[j]) {
+            constraints.add_edge(pages[i], pages[j], ());
+        }
+    }
+    for pair in keep.windows(2) {
+        constraints.add_edge(pair[0], pair[1], ());
+    }
+    let order = toposort(&constraints, None).ok()?;
+
+    // Place the moved pages from right to left. Everything after a moved page in
+    // the target order is already where it belongs, so we can anchor on its successor.
+    let mut moves = vec![];
+    for (i, &page) in order.iter().enumerate().rev() {
+        if !keep.contains(&page) {
+            moves.push(Move { page, before: order.get(i+1).copied() });
+        }
+    }
+
+    Some(Repair { keep, moves })
+}
+
+fn apply<'a>(pages: &[Page<'a>], moves: &[Move<'a>]) -> Vec<Page<'a>> {
+    let mut pages = pages.to_vec();
+    for m in moves {
+        pages.retain(|&page| page != m.page);
+        let position = match m.before {
+            Some(before) => pages.iter().position(|&page| page == before).unwrap(),
+            None => pages.len(),
+        };
+        pages.insert(position, m.page);
+    }
+    pages
+}
+
 #[cfg(test)]
 mod day05 {
     use super::*;
 
+    fn answers(input: &str) -> (u64, u64) {
+        let (rules, updates) = parse(input);
+        solve(&rules, &updates)
+    }
+
     const SAMPLE: &str = "47|53
 97|13
 97|61
@@ -87,11 +267,85 @@ mod day05 {
 
     #[test]
     fn test1() {
-        assert_eq!(solve(SAMPLE).0, 143)
+        assert_eq!(answers(SAMPLE).0, 143)
     }
  
     #[test]
     fn test2() {
-        assert_eq!(solve(SAMPLE).1, 123)
-    }   
+        assert_eq!(answers(SAMPLE).1, 123)
+    }
+
+    #[test]
+    fn analysis() {
+        let (rules, _) = parse(SAMPLE);
+        let analysis = analyze(&rules, &["75","97","47","61","53"]);
+        assert_eq!(analysis, Analysis {
+            violations: vec![("97","75")],
+            cycles: vec![],
+            missing: vec![],
+            order: Some(vec!["97","75","47","61","53"]),
+        });
+    }
+
+    #[test]
+    fn repairs() {
+        let (rules, updates) = parse(SAMPLE);
+        for pages in updates {
+            let order = analyze(&rules, &pages).order.unwrap();
+            let repair = repair(&rules, &pages).unwrap();
+            // The sample's rules order every pair, so there's only one answer.
+            assert_eq!(apply(&pages, &repair.moves), order);
+            assert_eq!(repair.keep.len() + repair.moves.len(), pages.len());
+        }
+
+        let repair = repair(&rules, &["97","13","75","29","47"]).unwrap();
+        assert_eq!(repair.keep.len(), 3);
+        let script: Vec<String> = repair.moves.iter().map(|m| m.to_string()).collect();
+        assert_eq!(script, vec!["move 13 to the end", "move 29 before 13"]);
+
+        // With no rule for b, moving c is enough, whichever order we aim for.
+        let (rules, _) = parse("a|c");
+        let repair = super::repair(&rules, &["c","a","b"]).unwrap();
+        assert_eq!(repair.moves.len(), 1);
+        assert!(analyze(&rules, &apply(&["c","a","b"], &repair.moves)).violations.is_empty());
+
+        // a has to come before b through x, so we can't keep both b and a.
+        let (rules, _) = parse("a|x\nx|b");
+        let repair = super::repair(&rules, &["b","x","a"]).unwrap();
+        assert_eq!(repair.moves.len(), 2);
+        assert_eq!(apply(&["b","x","a"], &repair.moves), ["a","x","b"]);
+        let (rules, _) = parse("a|b\nb|a");
+        assert_eq!(super::repair(&rules, &["a","b"]), None);
+    }
+
+    #[test]
+    fn cycles_and_missing() {
+        let input = "11|22\n22|33\n33|11\n11|44\n\n11,22,33,44\n22,11\n44,55";
+        let (rules, _) = parse(input);
+        let analysis = analyze(&rules, &["11","22","33","44"]);
+        assert_eq!(analysis.violations, vec![("33","11")]);
+        assert_eq!(analysis.cycles, vec![vec!["11","22","33"]]);
+        assert_eq!(analysis.missing, vec![("22","44"), ("33","44")]);
+        assert_eq!(analysis.order, None);
+
+        let analysis = analyze(&rules, &["44","55"]);
+        assert_eq!(analysis.missing, vec![("44","55")]);
+        assert!(analysis.violations.is_empty());
+
+        // The cyclic update is skipped instead of panicking.
+        assert_eq!(answers(input), (55, 22));
+    }
+
+    #[test]
+    fn layout() {
+        let input = "47|53\r\n97|13\r\n97|47\r\n 100 | 47 \r\n\r\n\r\n100,47, 53\r\nB,A\r\n\r\n  \r\n";
+        let (rules, updates) = parse(input);
+        assert_eq!(rules.edge_count(), 4);
+        assert_eq!(updates, vec![vec!["100", "47", "53"], vec!["B", "A"]]);
+        assert_eq!(unruled_pages(&rules, &updates), vec![(1, "B"), (1, "A")]);
+        // Named pages still get checked and repaired, they just don't add to the sums.
+        assert_eq!(answers("x|y\n\nx,y,z\ny,x"), (0, 0));
+        assert_eq!(answers("x|y\nx|12\n12|y\n\nx,12,y\ny,12,x"), (12, 12));
+        assert_eq!(answers(&SAMPLE.replace('\n', "\r\n")), (143, 123));
+    }
 }
\ No newline at end of file
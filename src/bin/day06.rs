@@ -1,199 +1,380 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fs,
-};
+use std::{collections::HashSet, fs};
+use rayon::prelude::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Guard {
-    x: i16,
-    y: i16,
-    dx: i16,
-    dy: i16,
+enum Heading {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Heading {
+    const ALL: [Heading; 4] = [Heading::Up, Heading::Right, Heading::Down, Heading::Left];
+
+    // -y is up, +y is down.
+    fn delta(self) -> (i32, i32) {
+        match self {
+            Heading::Up => (0, -1),
+            Heading::Right => (1, 0),
+            Heading::Down => (0, 1),
+            Heading::Left => (-1, 0),
+        }
+    }
+
+    fn turn(self, turn: Turn) -> Heading {
+        let quarters = match turn {
+            Turn::Right => 1,
+            Turn::Reverse => 2,
+            Turn::Left => 3,
+        };
+        Heading::ALL[(self as usize + quarters) % 4]
+    }
 }
 
-trait Go {
-    fn go(&mut self, world: &World) -> bool;
-    fn next(&self) -> (i16, i16);
-    fn turn(&mut self);
+// What the guard does when something is in the way.
+#[allow(dead_code)] // the puzzle only turns right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Turn {
+    Left,
+    Right,
+    Reverse,
 }
 
-enum MapElement {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
     Empty,
     Obstruction,
+    OneWay(Heading), // can only be entered moving this way
 }
 
-type World = HashMap<(i16, i16), MapElement>;
-
-impl Go for Guard {
-    fn go(&mut self, world: &World) -> bool {
-        match world.get(&self.next()) {
-            Some(MapElement::Empty) => {
-                self.x += self.dx;
-                self.y += self.dy;
-                true
-            }
-            Some(MapElement::Obstruction) => {
-                self.turn();
-                true
-            }
-            None => false,
+impl Tile {
+    fn blocks(self, heading: Heading) -> bool {
+        match self {
+            Tile::Empty => false,
+            Tile::Obstruction => true,
+            Tile::OneWay(allowed) => allowed != heading,
         }
     }
+}
 
-    fn next(&self) -> (i16, i16) {
-        (self.x + self.dx, self.y + self.dy)
-    }
+// The symbols on the map and how guards behave. The default is the puzzle,
+// plus a guard can start facing any direction and arrows are one-way tiles.
+struct Rules {
+    turn: Turn,
+    guards: Vec<(char, Heading)>,
+    one_way: Vec<(char, Heading)>,
+}
 
-    fn turn(&mut self) {
-        // https://wjholden.com/advent-of-code-2017-day22-part1.pdf
-        // Switch negative signs to invert (-y is up, +y is down).
-        (self.dx, self.dy) = (-self.dy, self.dx);
-        assert!(
-            (self.dx, self.dy) == (0, 1)
-                || (self.dx, self.dy) == (0, -1)
-                || (self.dx, self.dy) == (1, 0)
-                || (self.dx, self.dy) == (-1, 0)
-        );
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            turn: Turn::Right,
+            guards: vec![('^', Heading::Up), ('>', Heading::Right), ('v', Heading::Down), ('<', Heading::Left)],
+            one_way: vec![('↑', Heading::Up), ('→', Heading::Right), ('↓', Heading::Down), ('←', Heading::Left)],
+        }
     }
 }
 
-#[allow(dead_code)]
-fn show(world: &World, guard: &Guard, max_x: i16, max_y: i16) -> String {
-    let mut s = String::new();
-    (0..=max_y).for_each(|y| {
-        (0..=max_x).for_each(|x| {
-            if guard.x == x && guard.y == y {
-                s.push(match (guard.dx, guard.dy) {
-                    (0, -1) => '^',
-                    (1, 0) => '>',
-                    (0, 1) => 'v',
-                    (-1, 0) => '<',
-                    _ => panic!("unexpected guard direction"),
-                });
-            } else {
-                s.push(match world.get(&(x, y)).expect("map element in world") {
-                    MapElement::Empty => '.',
-                    MapElement::Obstruction => '#',
-                });
-            }
-        });
-        s.push('\n');
-    });
-    s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Guard {
+    position: usize, // index into the flat map
+    heading: Heading,
 }
 
-fn main() {
-    let puzzle = fs::read_to_string("puzzles/day06.txt").unwrap();
-    let result = solve(&puzzle);
-    println!("Part 1: {}", result.0);
-    println!("Part 2: {}", result.1);
+// Where a guard ends up walking straight from some cell: the last cell before an
+// obstruction, or the last cell before leaving the map.
+#[derive(Debug, Clone, Copy)]
+struct Jump {
+    stop: usize,
+    exits: bool,
 }
 
-fn solve(input: &str) -> (usize, usize) {
-    let mut max_x = 0;
-    let mut max_y = 0;
-    let mut guard = Guard {
-        x: 0,
-        y: 0,
-        dx: 0,
-        dy: -1,
-    };
-    let mut world = World::new();
-    input
-        .split_whitespace()
-        .enumerate()
-        .for_each(|(y, line)| {
-            line.char_indices().for_each(|(x, c)| {
-                max_x = max_x.max(x as i16);
-                max_y = max_y.max(y as i16);
-                world.insert(
-                    (x as i16, y as i16),
-                    match c {
-                        '.' => MapElement::Empty,
-                        '#' => MapElement::Obstruction,
-                        '^' => {
-                            guard.x = x as i16;
-                            guard.y = y as i16;
-                            MapElement::Empty
-                        }
-                        _ => unreachable!("the world should only contain symbols: .#^"),
-                    },
-                );
+struct Lab {
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    guards: Vec<Guard>,
+    turn: Turn,
+    jumps: [Vec<Jump>; 4], // indexed by heading, then position
+}
+
+impl Lab {
+    fn new(input: &str) -> Lab {
+        Lab::with_rules(input, &Rules::default())
+    }
+
+    fn with_rules(input: &str, rules: &Rules) -> Lab {
+        let lines: Vec<&str> = input.split_whitespace().collect();
+        let width = lines[0].chars().count();
+        let height = lines.len();
+        let mut tiles = vec![];
+        let mut guards = vec![];
+        let lookup = |symbols: &[(char, Heading)], c| symbols.iter().find(|(symbol, _)| *symbol == c).map(|&(_, heading)| heading);
+        for c in lines.iter().flat_map(|line| line.chars()) {
+            tiles.push(match c {
+                '.' => Tile::Empty,
+                '#' => Tile::Obstruction,
+                c => if let Some(heading) = lookup(&rules.guards, c) {
+                    guards.push(Guard { position: tiles.len(), heading });
+                    Tile::Empty
+                } else if let Some(heading) = lookup(&rules.one_way, c) {
+                    Tile::OneWay(heading)
+                } else {
+                    panic!("unexpected symbol {c} in the lab")
+                },
             });
         }
-    );
-        
-    let mut visited: HashSet<(i16, i16)> = HashSet::new();
-    let mut part2: HashSet<(i16, i16)> = HashSet::new();
-    let guard_initial = (guard.x, guard.y);
-    visited.insert(guard_initial);
-
-    loop {
-        // Part 2: try finding a cycle
-        let next = guard.next();
-        if next != guard_initial && !part2.contains(&next) {
-            if let Some(MapElement::Empty) = world.get(&next) {
-                world.insert(next, MapElement::Obstruction);
-                if is_cyclic_ttl(&world, guard_initial.0, guard_initial.1, max_x, max_y) {
-                    part2.insert(next);
+        assert!(!guards.is_empty(), "a guard in the lab");
+        let mut lab = Lab {
+            width,
+            height,
+            tiles,
+            guards,
+            turn: rules.turn,
+            jumps: [vec![], vec![], vec![], vec![]],
+        };
+        lab.jumps = Heading::ALL.map(|heading| lab.jump_table(heading));
+        lab
+    }
+
+    fn xy(&self, position: usize) -> (i32, i32) {
+        ((position % self.width) as i32, (position / self.width) as i32)
+    }
+
+    // The neighbouring cell in this direction, or None if that is off the map.
+    fn step(&self, position: usize, heading: Heading) -> Option<usize> {
+        let (x, y) = self.xy(position);
+        let (dx, dy) = heading.delta();
+        let (x, y) = (x + dx, y + dy);
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
+        }
+    }
+
+    // Each cell's jump is its neighbour's jump, so visit the cells in an order
+    // where the neighbour in this direction is always finished first.
+    fn jump_table(&self, heading: Heading) -> Vec<Jump> {
+        let n = self.tiles.len();
+        let mut table = vec![Jump { stop: 0, exits: false }; n];
+        let order: Box<dyn Iterator<Item = usize>> = match heading {
+            Heading::Up | Heading::Left => Box::new(0..n),
+            Heading::Down | Heading::Right => Box::new((0..n).rev()),
+        };
+        for i in order {
+            table[i] = match self.step(i, heading) {
+                None => Jump { stop: i, exits: true },
+                Some(next) if self.tiles[next].blocks(heading) => Jump { stop: i, exits: false },
+                Some(next) => table[next],
+            };
+        }
+        table
+    }
+
+    // The jump tables know nothing about the extra obstruction we're testing in
+    // part 2, so cut the run short if it lies between here and the stop.
+    fn jump(&self, guard: Guard, extra: usize) -> Jump {
+        let jump = self.jumps[guard.heading as usize][guard.position];
+        let (px, py) = self.xy(guard.position);
+        let (sx, sy) = self.xy(jump.stop);
+        let (ox, oy) = self.xy(extra);
+        let blocked = match guard.heading {
+            Heading::Up => ox == px && sy <= oy && oy < py,
+            Heading::Down => ox == px && py < oy && oy <= sy,
+            Heading::Left => oy == py && sx <= ox && ox < px,
+            Heading::Right => oy == py && px < ox && ox <= sx,
+        };
+        if blocked {
+            let (dx, dy) = guard.heading.delta();
+            let stop = ((oy - dy) * self.width as i32 + (ox - dx)) as usize;
+            Jump { stop, exits: false }
+        } else {
+            jump
+        }
+    }
+
+    // One move of the guard: a step forward or a turn. None if the guard leaves the map.
+    fn go(&self, guard: Guard, extra: Option<usize>) -> Option<Guard> {
+        let next = self.step(guard.position, guard.heading)?;
+        if self.tiles[next].blocks(guard.heading) || Some(next) == extra {
+            Some(Guard { position: guard.position, heading: guard.heading.turn(self.turn) })
+        } else {
+            Some(Guard { position: next, heading: guard.heading })
+        }
+    }
+
+    // Walks each guard one cell at a time and returns every cell any of them visits.
+    fn patrol(&self) -> Vec<usize> {
+        // One bit per heading, so that we also stop if a guard is already in a loop.
+        let mut seen = vec![0u8; self.tiles.len()];
+        for &start in self.guards.iter() {
+            let mut bits = vec![0u8; self.tiles.len()];
+            let mut guard = Some(start);
+            while let Some(g) = guard {
+                if bits[g.position] & (1 << g.heading as u8) != 0 {
+                    break
                 }
-                world.insert(next, MapElement::Empty);
+                bits[g.position] |= 1 << g.heading as u8;
+                seen[g.position] |= 1 << g.heading as u8;
+                guard = self.go(g, None);
             }
         }
+        (0..seen.len()).filter(|&i| seen[i] != 0).collect()
+    }
 
-        // Part 1
-        if guard.go(&world) {
-            visited.insert((guard.x, guard.y));
-        } else {
-            break;
+    // The guard only changes state at a turn, so it is looping exactly when it
+    // makes the same turn twice. Jumping from turn to turn makes each straight run O(1).
+    fn is_cyclic(&self, guard: Guard, extra: usize) -> bool {
+        let mut turns = HashSet::new();
+        let mut guard = guard;
+        loop {
+            let jump = self.jump(guard, extra);
+            if jump.exits {
+                return false
+            }
+            guard.position = jump.stop;
+            guard.heading = guard.heading.turn(self.turn);
+            if !turns.insert(guard) {
+                return true
+            }
         }
     }
 
-    (visited.len(), part2.len())
-}
+    // Brent's cycle detection over single moves. This is slower than is_cyclic, but
+    // it needs no memory and gives us the loop itself so we can see why it happens.
+    #[allow(dead_code)]
+    fn find_loop(&self, guard: Guard, extra: Option<usize>) -> Option<Loop> {
+        let f = |guard| self.go(guard, extra);
 
-#[allow(dead_code)]
-fn is_cyclic(world: &World, xi: i16, yi: i16) -> bool {
-    let mut guard = Guard {
-        x: xi,
-        y: yi,
-        dx: 0,
-        dy: -1,
-    };
-    let mut path: HashSet<Guard> = HashSet::new();
-    loop {
-        if !path.insert(guard) {
-            return true;
+        // Find the length of the loop by racing the hare ahead of the tortoise.
+        let mut power = 1;
+        let mut length = 1;
+        let mut tortoise = guard;
+        let mut hare = f(tortoise)?;
+        while tortoise != hare {
+            if power == length {
+                tortoise = hare;
+                power *= 2;
+                length = 0;
+            }
+            hare = f(hare)?;
+            length += 1;
         }
-        if !guard.go(world) {
-            return false;
+
+        // Then start them one loop apart. They meet where the guard enters the loop.
+        let mut tortoise = guard;
+        let mut hare = guard;
+        for _ in 0..length {
+            hare = f(hare)?;
+        }
+        while tortoise != hare {
+            tortoise = f(tortoise)?;
+            hare = f(hare)?;
         }
+
+        let mut states = vec![tortoise];
+        for _ in 1..length {
+            states.push(f(*states.last().unwrap())?);
+        }
+        Some(Loop { entry: tortoise, states })
     }
-}
 
-// You wouldn't think this would be faster, but you save so much copying and hashing
-// that just letting the CPU go brr is better.
-fn is_cyclic_ttl(world: &World, xi: i16, yi: i16, max_x: i16, max_y: i16) -> bool {
-    let mut guard = Guard {
-        x: xi,
-        y: yi,
-        dx: 0,
-        dy: -1,
-    };
-    let mut steps = 0;
-    while steps < world.len() {
-        if !guard.go(world) {
-            return false
+    #[allow(dead_code)]
+    fn show(&self, guard: &Guard, extra: Option<usize>, highlight: Option<&Loop>) -> String {
+        // Mark the loop the way the puzzle does: | and - for straight runs, + for turns.
+        let mut headings = vec![0u8; self.tiles.len()];
+        if let Some(highlight) = highlight {
+            for state in highlight.states.iter() {
+                headings[state.position] |= 1 << state.heading as u8;
+            }
         }
-        // If the guard escaped in the -x or -y direction then we are finished.
-        // 
-        // This surprisingly doesn't make much (any?) performance difference...
-        if guard.x < 0 || guard.x > max_x || guard.y < 0 || guard.y > max_y {
-            return false
+        let vertical = 1 << Heading::Up as u8 | 1 << Heading::Down as u8;
+        let mut s = String::new();
+        for (i, &tile) in self.tiles.iter().enumerate() {
+            if guard.position == i {
+                s.push(match guard.heading {
+                    Heading::Up => '^',
+                    Heading::Right => '>',
+                    Heading::Down => 'v',
+                    Heading::Left => '<',
+                });
+            } else if let Tile::Obstruction = tile {
+                s.push('#');
+            } else if let Tile::OneWay(heading) = tile {
+                s.push(match heading {
+                    Heading::Up => '↑',
+                    Heading::Right => '→',
+                    Heading::Down => '↓',
+                    Heading::Left => '←',
+                });
+            } else if extra == Some(i) {
+                s.push('O');
+            } else if headings[i] == 0 {
+                s.push('.');
+            } else if headings[i] & vertical == 0 {
+                s.push('-');
+            } else if headings[i] & !vertical == 0 {
+                s.push('|');
+            } else {
+                s.push('+');
+            }
+            if (i + 1) % self.width == 0 {
+                s.push('\n');
+            }
         }
-        steps += 1;
+        s
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Loop {
+    entry: Guard, // the first state on the loop that the guard reaches
+    states: Vec<Guard>, // one trip around the loop, starting from the entry
+}
+
+impl Loop {
+    // Turning in place counts as a move, so this can be more than the number of cells.
+    #[allow(dead_code)]
+    fn length(&self) -> usize {
+        self.states.len()
+    }
+
+    // Each cell on the loop once, in the order the guard first reaches it.
+    #[allow(dead_code)]
+    fn cells(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        self.states.iter().map(|state| state.position).filter(|&position| seen.insert(position)).collect()
     }
-    true
+}
+
+fn main() {
+    let puzzle = fs::read_to_string("puzzles/day06.txt").unwrap();
+    let result = solve(&puzzle);
+    println!("Part 1: {}", result.0);
+    println!("Part 2: {}", result.1);
+}
+
+fn solve(input: &str) -> (usize, usize) {
+    simulate(&Lab::new(input))
+}
+
+// With more than one guard, part 2 counts obstructions that make any of them loop.
+fn simulate(lab: &Lab) -> (usize, usize) {
+    let visited = lab.patrol();
+
+    // An obstruction can only change a guard's route if it is somewhere on it.
+    let part2 = visited
+        .par_iter()
+        .filter(|&&position| {
+            lab.tiles[position] == Tile::Empty
+                && lab.guards.iter().all(|guard| guard.position != position)
+                && lab.guards.iter().any(|&guard| lab.is_cyclic(guard, position))
+        })
+        .count();
+
+    (visited.len(), part2)
 }
 
 #[cfg(test)]
@@ -240,4 +421,117 @@ mod day06 {
     fn test3() {
         assert_eq!(solve(S2).1, 3)
     }
+
+    #[test]
+    fn loops() {
+        for input in [SAMPLE, S2] {
+            let lab = Lab::new(input);
+            let guard = lab.guards[0];
+            for position in lab.patrol() {
+                if position != guard.position {
+                    assert_eq!(lab.find_loop(guard, Some(position)).is_some(), lab.is_cyclic(guard, position));
+                }
+            }
+            assert_eq!(lab.find_loop(guard, None), None);
+        }
+    }
+
+    #[test]
+    fn show_loop() {
+        let lab = Lab::new(SAMPLE);
+        let extra = 6 * lab.width + 3;
+        let guard = lab.guards[0];
+        let highlight = lab.find_loop(guard, Some(extra)).unwrap();
+        assert_eq!(highlight.entry, guard);
+        assert_eq!(highlight.length(), 22);
+        assert_eq!(highlight.cells().len(), 18);
+        assert_eq!(lab.show(&guard, Some(extra), Some(&highlight)), "....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...
+");
+    }
+
+    #[test]
+    fn loop_entry() {
+        // The guard walks up two cells before it joins the loop.
+        let lab = Lab::new(".#...
+....#
+.....
+#....
+...#.
+.^...
+.....");
+        let highlight = lab.find_loop(lab.guards[0], None).unwrap();
+        assert_eq!(lab.xy(highlight.entry.position), (1, 3));
+        assert_eq!(highlight.entry.heading, Heading::Up);
+    }
+
+    #[test]
+    fn jumps() {
+        let lab = Lab::new(SAMPLE);
+        let start = lab.guards[0];
+        let jump = lab.jumps[Heading::Up as usize][start.position];
+        assert_eq!((lab.xy(jump.stop), jump.exits), ((4, 1), false));
+        let jump = lab.jumps[Heading::Left as usize][start.position];
+        assert_eq!((lab.xy(jump.stop), jump.exits), ((2, 6), false));
+        let jump = lab.jumps[Heading::Down as usize][start.position];
+        assert_eq!((lab.xy(jump.stop), jump.exits), ((4, 9), true));
+        // An extra obstruction at (4,3) stops the guard early.
+        let jump = lab.jump(start, 3 * lab.width + 4);
+        assert_eq!((lab.xy(jump.stop), jump.exits), ((4, 4), false));
+    }
+
+    #[test]
+    fn turn_rules() {
+        // Turning left mirrors the puzzle, so the answers must be the same.
+        let mirrored: String = SAMPLE.lines().map(|line| line.chars().rev().collect::<String>() + "\n").collect();
+        let rules = Rules { turn: Turn::Left, ..Rules::default() };
+        assert_eq!(simulate(&Lab::with_rules(&mirrored, &rules)), (41, 6));
+
+        // Rotating the map and the guard together also changes nothing.
+        let lines: Vec<Vec<char>> = SAMPLE.lines().map(|line| line.chars().collect()).collect();
+        let rotated: String = (0..lines[0].len()).map(|x| {
+            (0..lines.len()).rev().map(|y| if lines[y][x] == '^' { '>' } else { lines[y][x] }).collect::<String>() + "\n"
+        }).collect();
+        assert_eq!(solve(&rotated), (41, 6));
+
+        // Reversing sends the guard straight back down and off the map.
+        let rules = Rules { turn: Turn::Reverse, ..Rules::default() };
+        assert_eq!(simulate(&Lab::with_rules(SAMPLE, &rules)).0, 9);
+    }
+
+    #[test]
+    fn several_guards() {
+        let lab = Lab::new("..#..
+.....
+^...<
+.....");
+        assert_eq!(lab.guards.len(), 2);
+        assert_eq!(simulate(&lab).0, 7);
+    }
+
+    #[test]
+    fn one_way() {
+        // The guard can walk up through the arrow but not back down.
+        let input = ".#.
+...
+.↑.
+.^.";
+        let lab = Lab::new(input);
+        assert_eq!(simulate(&lab).0, 4);
+
+        // Reversing at the wall traps the guard above the arrow.
+        let rules = Rules { turn: Turn::Reverse, ..Rules::default() };
+        let lab = Lab::with_rules(input, &rules);
+        let highlight = lab.find_loop(lab.guards[0], None).unwrap();
+        assert_eq!(highlight.cells(), vec![4]);
+        assert_eq!(simulate(&lab), (3, 0));
+    }
 }
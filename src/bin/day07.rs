@@ -1,19 +1,30 @@
-use std::{fs, num::ParseIntError, str::FromStr};
+use std::{fmt::Display, fs, str::FromStr};
+use num::{BigUint, CheckedAdd, CheckedMul, CheckedSub, Integer};
 use rayon::prelude::*;
 
-struct Calibration {
-    res: u64,
-    val: Vec<u64>
+// The integer types we can solve equations in: u64 for the puzzle, u128 or
+// BigUint for longer equations. Everything goes through checked arithmetic.
+trait Value: Integer + Clone + CheckedAdd + CheckedMul + CheckedSub + From<u32> + FromStr + Display + Send + Sync {}
+
+impl<T> Value for T where T: Integer + Clone + CheckedAdd + CheckedMul + CheckedSub + From<u32> + FromStr + Display + Send + Sync {}
+
+// The answer doesn't fit in the integer type we chose.
+#[derive(Debug, PartialEq, Eq)]
+struct Overflow;
+
+struct Calibration<T> {
+    res: T,
+    val: Vec<T>
 }
 
-impl FromStr for Calibration {
-    type Err = ParseIntError;
+impl<T: Value> FromStr for Calibration<T> {
+    type Err = T::Err;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let line: Vec<&str> = s.split(":").collect();
         assert_eq!(line.len(), 2);
         let res = line[0].parse()?;
-        let val = line[1].split_whitespace().map(|s| s.parse().unwrap()).collect();
+        let val = line[1].split_whitespace().map(|s| s.parse()).collect::<Result<_, _>>()?;
         Ok(Self {
             res,
             val,
@@ -23,57 +34,318 @@ impl FromStr for Calibration {
 
 // Why is this solution so much faster than mine? 
 // https://www.reddit.com/r/adventofcode/comments/1h8l3z5/comment/m0wq0ta/
+// (It works backwards from the result. See Search::Backward.)
 fn main() {
-    //let time = std::time::Instant::now();
     let puzzle = fs::read_to_string("puzzles/day07.txt").unwrap();
-    let calibrations = parse(&puzzle);
-    println!("Part 1: {}", part1(&calibrations));
-    println!("Part 2: {}", part2(&calibrations));
-    //println!("Time: {:?}", time.elapsed());
+    // Pass "compare" to time part 2 with each search instead (the benchmark does).
+    let compare = std::env::args().nth(1).as_deref() == Some("compare");
+    // u64 is plenty for the puzzle and much faster, but fall back to BigUint if it isn't.
+    if parse::<u64>(&puzzle).map_err(|_| Overflow).and_then(|calibrations| run(&calibrations, compare)).is_err() {
+        run(&parse::<BigUint>(&puzzle).unwrap(), compare).unwrap();
+    }
 }
 
-fn part1(calibrations: &[Calibration]) -> u64 {
-    calibrations.par_iter().filter(|c| {
-        is_solvable1(c.val[0], &c.val[1..], c.res)
-    }).map(|c| c.res).sum()
+fn run<T: Value>(calibrations: &[Calibration<T>], compare: bool) -> Result<(), Overflow> {
+    if compare {
+        for search in [Search::Forward, Search::Backward] {
+            let time = std::time::Instant::now();
+            part2(calibrations, search)?;
+            println!("{:?} search: {:?}", search, time.elapsed());
+        }
+        return Ok(())
+    }
+    let p1 = part1(calibrations, Search::Backward)?;
+    let p2 = part2(calibrations, Search::Backward)?;
+    println!("Part 1: {}", p1);
+    println!("Part 2: {}", p2);
+    Ok(())
 }
 
-fn part2(calibrations: &[Calibration]) -> u64 {
-    calibrations.par_iter().filter(|c| {
-        is_solvable2(c.val[0], &c.val[1..], c.res)
-    }).map(|c| c.res).sum()
+#[derive(Debug, Clone, Copy)]
+enum Search {
+    Forward, // try every operator from the left
+    Backward, // undo operators from the right, starting at the result
 }
 
-fn is_solvable1(left: u64, right: &[u64], target: u64) -> bool {
-    if right.is_empty() {
-        left == target
-    } else if left > target {
+fn part1<T: Value>(calibrations: &[Calibration<T>], search: Search) -> Result<T, Overflow> {
+    total(calibrations, &[&Add, &Mul], search)
+}
+
+fn part2<T: Value>(calibrations: &[Calibration<T>], search: Search) -> Result<T, Overflow> {
+    total(calibrations, &[&Add, &Mul, &Concat { base: 10 }], search)
+}
+
+fn total<T: Value>(calibrations: &[Calibration<T>], operators: &[&dyn Operator<T>], search: Search) -> Result<T, Overflow> {
+    let solved: Vec<Option<&T>> = calibrations.par_iter().map(|c| {
+        Ok(solve(c, operators, search)?.map(|_| &c.res))
+    }).collect::<Result<_, _>>()?;
+    solved.into_iter().flatten().try_fold(T::zero(), |sum, res| sum.checked_add(res).ok_or(Overflow))
+}
+
+// Operators are always evaluated left-to-right, ignoring precedence.
+trait Operator<T>: Sync {
+    fn symbol(&self) -> String;
+
+    // Ok(None) if there is no answer at all (negative or fractional),
+    // and Err if there is one but it doesn't fit in T.
+    fn apply(&self, left: &T, right: &T) -> Result<Option<T>, Overflow>;
+
+    // The left operand that would give us this result, if there is one.
+    fn undo(&self, result: &T, right: &T) -> Result<Option<T>, Overflow>;
+
+    // True if every left operand gives this result, like x * 0 = 0, so there's
+    // no single one for undo to return.
+    fn absorbs(&self, _result: &T, _right: &T) -> bool {
         false
-    } else {
-        let current = right[0];
-        is_solvable1(left + current, &right[1..], target) ||
-        is_solvable1(left * current, &right[1..], target)
+    }
+
+    // True if the result is never less than the left operand, so we can stop
+    // searching once we pass the target. This assumes operands are positive.
+    fn increasing(&self) -> bool {
+        false
+    }
+}
+
+struct Add;
+struct Mul;
+#[allow(dead_code)] // not used by the puzzle
+struct Sub;
+#[allow(dead_code)]
+struct Div;
+struct Concat {
+    base: u32,
+}
+
+impl<T: Value> Operator<T> for Add {
+    fn symbol(&self) -> String {
+        "+".to_string()
+    }
+
+    fn apply(&self, left: &T, right: &T) -> Result<Option<T>, Overflow> {
+        left.checked_add(right).map(Some).ok_or(Overflow)
+    }
+
+    fn undo(&self, result: &T, right: &T) -> Result<Option<T>, Overflow> {
+        Ok(result.checked_sub(right))
+    }
+
+    fn increasing(&self) -> bool {
+        true
+    }
+}
+
+impl<T: Value> Operator<T> for Mul {
+    fn symbol(&self) -> String {
+        "*".to_string()
+    }
+
+    fn apply(&self, left: &T, right: &T) -> Result<Option<T>, Overflow> {
+        left.checked_mul(right).map(Some).ok_or(Overflow)
+    }
+
+    fn undo(&self, result: &T, right: &T) -> Result<Option<T>, Overflow> {
+        Div.apply(result, right)
+    }
+
+    fn absorbs(&self, result: &T, right: &T) -> bool {
+        result.is_zero() && right.is_zero()
+    }
+
+    fn increasing(&self) -> bool {
+        true
     }
 }
 
-fn is_solvable2(left: u64, right: &[u64], target: u64) -> bool {
+impl<T: Value> Operator<T> for Sub {
+    fn symbol(&self) -> String {
+        "-".to_string()
+    }
+
+    fn apply(&self, left: &T, right: &T) -> Result<Option<T>, Overflow> {
+        Ok(left.checked_sub(right))
+    }
+
+    fn undo(&self, result: &T, right: &T) -> Result<Option<T>, Overflow> {
+        result.checked_add(right).map(Some).ok_or(Overflow)
+    }
+}
+
+// Only exact division, since integer division loses information.
+impl<T: Value> Operator<T> for Div {
+    fn symbol(&self) -> String {
+        "/".to_string()
+    }
+
+    fn apply(&self, left: &T, right: &T) -> Result<Option<T>, Overflow> {
+        if right.is_zero() || !left.is_multiple_of(right) {
+            Ok(None)
+        } else {
+            Ok(Some(left.clone() / right.clone()))
+        }
+    }
+
+    fn undo(&self, result: &T, right: &T) -> Result<Option<T>, Overflow> {
+        if right.is_zero() {
+            Ok(None)
+        } else {
+            result.checked_mul(right).map(Some).ok_or(Overflow)
+        }
+    }
+}
+
+impl Concat {
+    // How far left has to move to make room for the digits of right. I used to
+    // use ilog10 here, but multiplying works for any integer type (even BigUint)
+    // and we only ever need a handful of steps.
+    fn shift<T: Value>(&self, right: &T) -> Result<T, Overflow> {
+        // In base 0 or 1 the shift never grows past right, so we'd loop forever.
+        assert!(self.base >= 2, "can't concatenate in base {}", self.base);
+        let base = T::from(self.base);
+        let mut shift = base.clone();
+        while shift <= *right {
+            shift = shift.checked_mul(&base).ok_or(Overflow)?;
+        }
+        Ok(shift)
+    }
+}
+
+impl<T: Value> Operator<T> for Concat {
+    fn symbol(&self) -> String {
+        match self.base {
+            10 => "||".to_string(),
+            base => format!("||{base}"),
+        }
+    }
+
+    fn apply(&self, left: &T, right: &T) -> Result<Option<T>, Overflow> {
+        let shifted = left.checked_mul(&self.shift(right)?).ok_or(Overflow)?;
+        shifted.checked_add(right).map(Some).ok_or(Overflow)
+    }
+
+    // Strip the digits of right off the end of the result.
+    fn undo(&self, result: &T, right: &T) -> Result<Option<T>, Overflow> {
+        match self.shift(right) {
+            Ok(shift) => {
+                let (left, suffix) = result.div_rem(&shift);
+                Ok(if suffix == *right { Some(left) } else { None })
+            },
+            // The shift doesn't fit, so neither can any result with a nonzero left.
+            Err(Overflow) => Ok(if result == right { Some(T::zero()) } else { None }),
+        }
+    }
+
+    fn increasing(&self) -> bool {
+        true
+    }
+}
+
+type Solution<'a, T> = Result<Option<Vec<&'a dyn Operator<T>>>, Overflow>;
+
+// Finds operators that make the calibration true, if there are any. If we don't
+// find any, but had to skip some branches because they overflowed, then we
+// can't really say there is no answer, so that's an error.
+fn solve<'a, T: Value>(c: &Calibration<T>, operators: &[&'a dyn Operator<T>], search: Search) -> Solution<'a, T> {
+    let mut overflowed = false;
+    let used = match search {
+        Search::Forward => {
+            // Multiplying by 0 comes back down, so a zero operand rules out stopping early.
+            let increasing = operators.iter().all(|op| op.increasing()) && !c.val.iter().any(T::is_zero);
+            forward(c.val[0].clone(), &c.val[1..], &c.res, operators, increasing, &mut overflowed).map(|mut used| {
+                used.reverse();
+                used
+            })
+        },
+        Search::Backward => backward(c.res.clone(), &c.val, operators, &mut overflowed),
+    };
+    match used {
+        None if overflowed => Err(Overflow),
+        used => Ok(used),
+    }
+}
+
+// Returns the operators used in reverse order, which saves shifting the vector.
+fn forward<'a, T: Value>(left: T, right: &[T], target: &T, operators: &[&'a dyn Operator<T>], increasing: bool, overflowed: &mut bool) -> Option<Vec<&'a dyn Operator<T>>> {
     if right.is_empty() {
-        left == target
-    } else if left > target {
-        false // our operators only increase the value, so stop early if we've already overflowed  
+        if left == *target { Some(vec![]) } else { None }
+    } else if increasing && left > *target {
+        None // our operators only increase the value, so stop early if we've already overflowed
     } else {
-        let current = right[0];
-        // https://www.reddit.com/r/adventofcode/comments/1h8l3z5/comment/m0vp3p7/
-        //let digits = 1 + (current as f64).log10().floor() as u32;
-        let digits = 1 + current.ilog10(); // WOW, this is a lot faster. 
-        is_solvable2(left + current, &right[1..], target) ||
-        is_solvable2(left * current, &right[1..], target) ||
-        is_solvable2(left * 10u64.pow(digits) + current, &right[1..], target)
+        let current = &right[0];
+        operators.iter().find_map(|&op| {
+            let next = match op.apply(&left, current) {
+                Ok(next) => next?,
+                // Past the target, so this is only a problem if we could come back down.
+                Err(Overflow) => {
+                    *overflowed |= !increasing;
+                    return None
+                },
+            };
+            let mut used = forward(next, &right[1..], target, operators, increasing, overflowed)?;
+            used.push(op);
+            Some(used)
+        })
+    }
+}
+
+// Most operators can't be undone for most values (the result has to be divisible,
+// or end with the right digits), so nearly every branch dies immediately.
+fn backward<'a, T: Value>(target: T, values: &[T], operators: &[&'a dyn Operator<T>], overflowed: &mut bool) -> Option<Vec<&'a dyn Operator<T>>> {
+    let (last, rest) = values.split_last()?;
+    if rest.is_empty() {
+        return if target == *last { Some(vec![]) } else { None }
     }
+    operators.iter().find_map(|&op| {
+        if op.absorbs(&target, last) {
+            // Whatever the rest comes to works, as long as it comes to something.
+            let mut used = evaluate(rest[0].clone(), &rest[1..], operators, overflowed)?;
+            used.reverse();
+            used.push(op);
+            return Some(used)
+        }
+        let previous = match op.undo(&target, last) {
+            Ok(previous) => previous?,
+            Err(Overflow) => {
+                *overflowed = true;
+                return None
+            },
+        };
+        let mut used = backward(previous, rest, operators, overflowed)?;
+        used.push(op);
+        Some(used)
+    })
 }
 
-fn parse(input: &str) -> Vec<Calibration> {
-    input.trim().split('\n').map(|line| Calibration::from_str(line).unwrap()).collect()
+// Any way of combining the values that doesn't fail, with the operators in
+// reverse order like forward.
+fn evaluate<'a, T: Value>(left: T, right: &[T], operators: &[&'a dyn Operator<T>], overflowed: &mut bool) -> Option<Vec<&'a dyn Operator<T>>> {
+    let Some((current, right)) = right.split_first() else {
+        return Some(vec![])
+    };
+    operators.iter().find_map(|&op| {
+        let next = match op.apply(&left, current) {
+            Ok(next) => next?,
+            Err(Overflow) => {
+                *overflowed = true;
+                return None
+            },
+        };
+        let mut used = evaluate(next, right, operators, overflowed)?;
+        used.push(op);
+        Some(used)
+    })
+}
+
+#[allow(dead_code)]
+fn expression<T: Value>(values: &[T], operators: &[&dyn Operator<T>]) -> String {
+    let mut s = values[0].to_string();
+    for (op, value) in operators.iter().zip(&values[1..]) {
+        s += &format!(" {} {}", op.symbol(), value);
+    }
+    s
+}
+
+fn parse<T: Value>(input: &str) -> Result<Vec<Calibration<T>>, T::Err> {
+    input.trim().split('\n').map(Calibration::from_str).collect()
 }
 
 #[cfg(test)]
@@ -91,18 +363,137 @@ mod day07 {
 292: 11 6 16 20
 ";
 
+    fn calibrations(input: &str) -> Vec<Calibration<u64>> {
+        parse(input).unwrap()
+    }
+
     #[test]
     fn test1() {
-        assert_eq!(part1(&parse(SAMPLE)), 3749)
+        assert_eq!(part1(&calibrations(SAMPLE), Search::Forward), Ok(3749));
+        assert_eq!(part1(&calibrations(SAMPLE), Search::Backward), Ok(3749));
     }
  
     #[test]
     fn test2() {
-        assert_eq!(part2(&parse(SAMPLE)), 11387)
+        assert_eq!(part2(&calibrations(SAMPLE), Search::Forward), Ok(11387));
+        assert_eq!(part2(&calibrations(SAMPLE), Search::Backward), Ok(11387));
     }   
 
     #[test]
     fn test3() {
-        assert_eq!(part2(&parse("192: 17 8 14")), 192);
+        assert_eq!(part2(&calibrations("192: 17 8 14"), Search::Forward), Ok(192));
+        assert_eq!(part2(&calibrations("192: 17 8 14"), Search::Backward), Ok(192));
+    }
+
+    #[test]
+    fn expressions() {
+        let c = &calibrations(SAMPLE)[1];
+        let used = solve(c, &[&Add, &Mul], Search::Forward).unwrap().unwrap();
+        assert_eq!(expression(&c.val, &used), "81 + 40 * 27");
+        // This one has two solutions, and going backwards finds the other one first.
+        let used = solve(c, &[&Add, &Mul], Search::Backward).unwrap().unwrap();
+        assert_eq!(expression(&c.val, &used), "81 * 40 + 27");
+
+        let c = &calibrations("7290: 6 8 6 15")[0];
+        for search in [Search::Forward, Search::Backward] {
+            assert!(solve(c, &[&Add, &Mul], search).unwrap().is_none());
+            let used = solve(c, &[&Add, &Mul, &Concat { base: 10 }], search).unwrap().unwrap();
+            assert_eq!(expression(&c.val, &used), "6 * 8 || 6 * 15");
+        }
+    }
+
+    #[test]
+    fn other_operators() {
+        let c = &calibrations("2: 10 2 3 2")[0];
+        for search in [Search::Forward, Search::Backward] {
+            assert!(solve(c, &[&Add, &Mul], search).unwrap().is_none());
+            let used = solve(c, &[&Add, &Sub, &Div], search).unwrap().unwrap();
+            assert_eq!(expression(&c.val, &used), "10 + 2 / 3 - 2");
+
+            // 0b101 || 0b11 is 0b10111
+            let c = &calibrations("23: 5 3")[0];
+            let used = solve(c, &[&Concat { base: 2 }], search).unwrap().unwrap();
+            assert_eq!(expression(&c.val, &used), "5 ||2 3");
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        // 5000000000 * 5000000000 is too big for a u64, even though the answer isn't.
+        let input = "15000000000000000000: 5000000000 5000000000 10000000000000000000";
+        let c = &calibrations(input)[0];
+        for search in [Search::Forward, Search::Backward] {
+            assert_eq!(solve(c, &[&Mul, &Sub], search).err(), Some(Overflow));
+            // With only increasing operators, overflowing just means we passed the target.
+            assert!(solve(c, &[&Add, &Mul], search).unwrap().is_none());
+        }
+        let c = &parse::<u128>(input).unwrap()[0];
+        for search in [Search::Forward, Search::Backward] {
+            let used = solve(c, &[&Mul, &Sub], search).unwrap().unwrap();
+            assert_eq!(expression(&c.val, &used), "5000000000 * 5000000000 - 10000000000000000000");
+        }
+
+        // The sum of the results doesn't fit either.
+        let input = "10000000000000000000: 10000000000000000000\n10000000000000000000: 10000000000000000000";
+        assert_eq!(part1(&calibrations(input), Search::Backward), Err(Overflow));
+        assert_eq!(part1(&parse::<u128>(input).unwrap(), Search::Backward), Ok(20000000000000000000));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bignum() {
+        let input = "123456789123456789123456789123456789123456789: 123456789123456789 123456789123456789 123456789";
+        assert!(parse::<u64>(input).is_err());
+        assert!(parse::<u128>(input).is_err());
+        let c = &parse::<BigUint>(input).unwrap()[0];
+        let used = solve(c, &[&Add, &Mul, &Concat { base: 10 }], Search::Forward).unwrap().unwrap();
+        assert_eq!(expression(&c.val, &used), "123456789123456789 || 123456789123456789 || 123456789");
+        assert_eq!(part2(&parse::<BigUint>(SAMPLE).unwrap(), Search::Backward), Ok(BigUint::from(11387u32)));
+    }
+
+    #[test]
+    fn zeros() {
+        let c = &calibrations("0: 5 0")[0];
+        for search in [Search::Forward, Search::Backward] {
+            let used = solve(c, &[&Mul, &Sub], search).unwrap().unwrap();
+            assert_eq!(expression(&c.val, &used), "5 * 0");
+            assert!(solve(c, &[&Add, &Sub], search).unwrap().is_none());
+        }
+        // Going backwards, the 0 result says nothing about 7 - 9, which can't be done.
+        let c = &calibrations("0: 7 9 0")[0];
+        for search in [Search::Forward, Search::Backward] {
+            let used = solve(c, &[&Sub, &Mul], search).unwrap().unwrap();
+            assert_eq!(expression(&c.val, &used), "7 * 9 * 0");
+        }
+    }
+
+    #[test]
+    fn searches_agree() {
+        let operators: [&[&dyn Operator<u64>]; 4] = [&[&Add, &Mul], &[&Add, &Mul, &Concat { base: 10 }], &[&Mul, &Sub], &[&Sub, &Div, &Concat { base: 2 }]];
+        let evaluate = |values: &[u64], used: &[&dyn Operator<u64>]| {
+            used.iter().zip(&values[1..]).try_fold(values[0], |left, (op, right)| op.apply(&left, right).unwrap())
+        };
+        // Every list of up to four values from 0 to 3, against every result up to 20.
+        for length in 1..=4u32 {
+            for digits in 0..4u64.pow(length) {
+                let val: Vec<u64> = (0..length).map(|i| digits / 4u64.pow(i) % 4).collect();
+                for res in 0..=20 {
+                    let c = Calibration { res, val: val.clone() };
+                    for operators in operators {
+                        let forward = solve(&c, operators, Search::Forward).unwrap();
+                        let backward = solve(&c, operators, Search::Backward).unwrap();
+                        assert_eq!(forward.is_some(), backward.is_some(), "{res}: {val:?}");
+                        for used in forward.iter().chain(backward.iter()) {
+                            assert_eq!(evaluate(&c.val, used), Some(res), "{}", expression(&c.val, used));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't concatenate in base 1")]
+    fn unary_concat() {
+        let _ = Operator::<u64>::apply(&Concat { base: 1 }, &12, &34);
+    }
+}
@@ -1,12 +1,13 @@
-use std::{collections::HashSet, fs};
+use std::{collections::{HashMap, HashSet}, fs};
 use grid::*;
 use itertools::Itertools;
+use num::integer::gcd;
 
 fn main() {
     let puzzle = fs::read_to_string("puzzles/day08.txt").unwrap();
     let solution = part1(&puzzle);
     println!("Part 1: {}", solution.0);
-    println!("Part 1: {}", solution.1);
+    println!("Part 2: {}", solution.1);
 }
 
 fn parse(input: &str) -> Grid<char> {
@@ -37,64 +38,88 @@ fn get_frequencies(grid: &Grid<char>) -> HashSet<char> {
     }).dedup().collect()
 }
 
+// Antinode positions for each frequency.
+type Antinodes = HashMap<char, HashSet<(i32,i32)>>;
+
+// Where a pair of antennas creates antinodes. All of these work for antennas in
+// any direction from each other, including the same row or column.
+#[allow(dead_code)] // the puzzle only needs Harmonics(vec![2]) and WholeLine
+#[derive(Debug, Clone)]
+enum Resonance {
+    // Points in line with both antennas, outside the pair, where one is k times
+    // as far away as the other. Part 1 is k = 2.
+    Harmonics(Vec<i32>),
+    // Like Harmonics, but the points between the antennas, if they are grid points.
+    Between(Vec<i32>),
+    // Every grid point in line with both antennas (part 2).
+    WholeLine,
+    // Grid points between the antennas and up to this many steps beyond each one.
+    Steps(i32),
+    // The point halfway between the antennas, if it is a grid point.
+    Midpoint,
+}
+
+impl Resonance {
+    fn antinodes(&self, (x1,y1): (i32,i32), (x2,y2): (i32,i32), is_in_bounds: impl Fn((i32,i32)) -> bool) -> Vec<(i32,i32)> {
+        let (dx,dy) = (x2 - x1, y2 - y1);
+        // The point num/den of the way from the first antenna to the second, if that is a grid point.
+        let at = |num: i32, den: i32| {
+            if (dx*num) % den == 0 && (dy*num) % den == 0 {
+                Some((x1 + dx*num/den, y1 + dy*num/den))
+            } else {
+                None
+            }
+        };
+        // Dividing out the gcd gives us the smallest step along the line, so we
+        // don't skip any grid points between the antennas.
+        let g = gcd(dx, dy);
+        let (sx,sy) = (dx/g, dy/g);
+        let step = |j: i32| (x1 + j*sx, y1 + j*sy);
+
+        let points: Vec<(i32,i32)> = match self {
+            // Solve |t| = k|t-1| and |t-1| = k|t| for t. Outside the pair there's
+            // only an answer for k > 1, and between them there's one for any k.
+            Resonance::Harmonics(ks) => ks.iter().filter(|&&k| k > 1).flat_map(|&k| {
+                [(k, k - 1), (-1, k - 1)].into_iter().filter_map(|(num, den)| at(num, den))
+            }).collect(),
+            Resonance::Between(ks) => ks.iter().filter(|&&k| k >= 1).flat_map(|&k| {
+                [(k, k + 1), (1, k + 1)].into_iter().filter_map(|(num, den)| at(num, den))
+            }).collect(),
+            Resonance::WholeLine => {
+                let forward = (0..).map(step).take_while(|&p| is_in_bounds(p));
+                let backward = (1..).map(|j| step(-j)).take_while(|&p| is_in_bounds(p));
+                forward.chain(backward).collect()
+            },
+            Resonance::Steps(n) => (-n..=g + n).map(step).collect(),
+            Resonance::Midpoint => at(1, 2).into_iter().collect(),
+        };
+        points.into_iter().filter(|&p| is_in_bounds(p)).unique().collect()
+    }
+}
+
 fn part1(input: &str) -> (usize, usize) {
     let grid = parse(input);
-    let is_in_bounds = |x,y| {
+    let count = |rule| antinodes(&grid, &rule, None).into_values().flatten().unique().count();
+    (count(Resonance::Harmonics(vec![2])), count(Resonance::WholeLine))
+}
+
+// Antinodes for every pair of antennas with the same frequency. Pass some
+// frequencies to only look at those.
+fn antinodes(grid: &Grid<char>, rule: &Resonance, frequencies: Option<&[char]>) -> Antinodes {
+    let is_in_bounds = |(x,y): (i32,i32)| {
         0 <= x && x < grid.rows() as i32 && 0 <= y && y < grid.cols() as i32
     };
-    let mut antinodes = HashSet::new();
-    let mut antinodes2 = HashSet::new();
-    for frequency in get_frequencies(&grid) {
-        for pair in find_antennas(&grid, frequency).into_iter().combinations(2) {
-            let (x1,y1) = pair[0];
-            let (x2,y2) = pair[1];
-
-            assert!((x1 != x2) && (y1 != y2));
-
-            let dx = (x2 - x1).abs();
-            let dy = (y2 - y1).abs();
-            let slope = (x2 - x1)/dx * (y2 - y1)/dy;
-
-            for i in 0.. {
-                let dx = dx * i;
-                let dy = dy * i;
-
-                let (left, right) = match (dx,dy) {
-                    (0,0) => ((x1,y1), (x2,y2)), // for part 2 only
-                    (0,_) => ((x1, y1.min(y2) - dy), (x2, y1.max(y2) + dy)),
-                    (_,0) => ((x1.min(x2) - dx, y1), (x1.max(x2) + dx, y2)),
-                    (dx,dy) if slope == -1 => /* negative slope */
-                        ((x1.min(x2) - dx, y1.max(y2) + dy),
-                        (x1.max(x2) + dx, y1.min(y2) - dy)),
-                    (dx,dy) if slope == 1 => /* positive slope */
-                        ((x1.min(x2) - dx, y1.min(y2) - dy),
-                        (x1.max(x2) + dx, y1.max(y2) + dy)),
-                    _ => unreachable!("we should never have used the default match arm")
-                };
-
-                if is_in_bounds(left.0, left.1) {
-                    if i == 1 {
-                        antinodes.insert(left);
-                    }
-                    antinodes2.insert(left);
-                }
-
-                if is_in_bounds(right.0, right.1) {
-                    if i == 1 {
-                        antinodes.insert(right);
-                    }
-                    antinodes2.insert(right);
-                }
-
-                if !is_in_bounds(left.0, left.1) && 
-                    !is_in_bounds(right.0, right.1) {
-                        break
-                }
-            }
+    let mut antinodes = Antinodes::new();
+    for frequency in get_frequencies(grid) {
+        if frequencies.is_some_and(|frequencies| !frequencies.contains(&frequency)) {
+            continue
+        }
+        let found = antinodes.entry(frequency).or_default();
+        for pair in find_antennas(grid, frequency).into_iter().combinations(2) {
+            found.extend(rule.antinodes(pair[0], pair[1], is_in_bounds));
         }
     }
-    
-    (antinodes.len(), antinodes2.len())
+    antinodes
 }
 
 #[allow(dead_code)]
@@ -113,6 +138,11 @@ fn map(input: &str, antinodes: &HashSet<(i32,i32)>) -> String {
     s
 }
 
+#[allow(dead_code)]
+fn overlay(input: &str, antinodes: &Antinodes) -> String {
+    map(input, &antinodes.values().flatten().copied().collect())
+}
+
 #[cfg(test)]
 mod day08 {
     use super::*;
@@ -204,6 +234,77 @@ const SAMPLE5: &str = "T....#....
         assert_eq!(part1(SAMPLE5).1, 9)
     }   
 
+    #[test]
+    fn same_row_and_column() {
+        assert_eq!(part1("..a.a..."), (2, 8));
+        assert_eq!(part1(".\na\n.\n.\na\n.\n.\n.\n."), (1, 9));
+        // The points a third of the way between antennas three apart don't count.
+        assert_eq!(part1("...a..a..."), (2, 10));
+    }
+
+    #[test]
+    fn reduced_step() {
+        // The difference between these is (2,4), so the line also passes through (1,2) and (3,6).
+        let input = "a.......
+........
+....a...
+........
+........";
+        let antinodes = antinodes(&parse(input), &Resonance::WholeLine, None);
+        assert_eq!(antinodes[&'a'], HashSet::from([(0,0), (1,2), (2,4), (3,6)]));
+    }
+
+    #[test]
+    fn by_frequency() {
+        let grid = parse(SAMPLE);
+        let antinodes1 = antinodes(&grid, &Resonance::Harmonics(vec![2]), None);
+        assert_eq!(antinodes1.keys().copied().collect::<HashSet<char>>(), HashSet::from(['A', '0']));
+        assert!(antinodes1[&'A'].contains(&(7, 7)));
+        assert!(!antinodes1[&'0'].contains(&(7, 7)));
+        let antinodes2 = antinodes(&grid, &Resonance::WholeLine, None);
+        assert_eq!(antinodes2[&'A'].len() + antinodes2[&'0'].len() - antinodes2[&'A'].intersection(&antinodes2[&'0']).count(), 34);
+        let only_a = antinodes(&grid, &Resonance::WholeLine, Some(&['A']));
+        assert_eq!(only_a.keys().copied().collect::<Vec<char>>(), vec!['A']);
+        assert_eq!(only_a[&'A'], antinodes2[&'A']);
+    }
+
+    #[test]
+    fn resonance() {
+        let grid = parse("a...a...........");
+        let cols = |rule| {
+            let mut cols: Vec<i32> = antinodes(&grid, &rule, None)[&'a'].iter().map(|&(_, y)| y).collect();
+            cols.sort();
+            cols
+        };
+        assert_eq!(cols(Resonance::Harmonics(vec![2])), vec![8]);
+        assert_eq!(cols(Resonance::Harmonics(vec![3])), vec![6]);
+        assert_eq!(cols(Resonance::Harmonics(vec![2, 3])), vec![6, 8]);
+        assert_eq!(cols(Resonance::Between(vec![3])), vec![1, 3]);
+        assert_eq!(cols(Resonance::Between(vec![2])), vec![]);
+        assert_eq!(cols(Resonance::Between(vec![1])), cols(Resonance::Midpoint));
+        assert_eq!(cols(Resonance::Midpoint), vec![2]);
+        assert_eq!(cols(Resonance::Steps(0)), vec![0, 1, 2, 3, 4]);
+        assert_eq!(cols(Resonance::Steps(2)), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(cols(Resonance::WholeLine), (0..16).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn overlay_one_frequency() {
+        // The # in this sample is an antenna, so only draw the T antinodes.
+        let antinodes = antinodes(&parse(SAMPLE5), &Resonance::WholeLine, Some(&['T']));
+        assert_eq!(overlay(SAMPLE5, &antinodes), "#....#....
+...#......
+.#....#...
+.........#
+..#.......
+..........
+...#......
+..........
+....#.....
+..........
+");
+    }
+
     #[test]
     fn grid() {
         let grid = parse(SAMPLE);
@@ -1,10 +1,29 @@
-use std::fmt;
+use std::{cmp::Reverse, collections::BinaryHeap, fmt};
 
 fn main() {
     let puzzle = std::fs::read_to_string("puzzles/day09.txt").unwrap();
     let mut disk = parse(&puzzle);
     println!("Part 1: {}", part1(&mut disk));
     println!("Part 2: {}", part2(&puzzle));
+    // Pass "strategies" to see how each one leaves the disk.
+    if std::env::args().nth(1).as_deref() == Some("strategies") {
+        for strategy in [Strategy::FirstFit, Strategy::BestFit, Strategy::WorstFit, Strategy::Fragmenting] {
+            let report = compact(&puzzle, strategy);
+            println!("{:?}: checksum {}, {} free spans, largest free span {}",
+                strategy, report.checksum, report.free_spans, report.largest_free_span);
+        }
+    }
+    // Pass "moves" to print part 2's move log and replay it on a fresh disk.
+    if std::env::args().nth(1).as_deref() == Some("moves") {
+        let mut disk = Disk2::new(&puzzle);
+        let moves = disk.defrag(Strategy::FirstFit);
+        moves.iter().for_each(|step| println!("{step}"));
+        let mut replayed = Disk2::new(&puzzle);
+        match replayed.replay(&moves) {
+            Ok(()) => println!("Replayed {} moves, {} blocks differ", moves.len(), replayed.diff(&disk).len()),
+            Err(error) => println!("Replay failed: {error}"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -17,10 +36,12 @@ enum Block {
 
 type Disk = Vec<Block>;
 
+// IDs past 9 are wrapped in brackets so a picture can be read back unambiguously.
 impl fmt::Display for Block {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Block::Data{id} => write!(f, "{id}"),
+            Block::Data{id} if *id < 10 => write!(f, "{id}"),
+            Block::Data{id} => write!(f, "({id})"),
             Block::Empty => write!(f, ".")
         }
     }
@@ -70,6 +91,7 @@ fn part1(disk: &mut [Block]) -> u64 {
     checksum(disk)
 }
 
+// A file, or a piece of one if the disk has been fragmented.
 #[derive(Debug)]
 struct File {
     id: usize,
@@ -85,21 +107,55 @@ struct Free {
 struct Disk2 {
     files: Vec<File>,
     frees: Vec<Free>,
-    _size: usize
+    size: usize
 }
 
 impl fmt::Display for Disk2 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut s = String::new();
-        for _ in 0..self._size {
-            s.push('.');
-        }
-        for file in self.files.iter() {
-            for i in file.position..file.position+file.length {
-                s.replace_range(i..i+1, &file.id.to_string());
+        for block in self.blocks() {
+            match block {
+                Some(id) => write!(f, "{}", Block::Data{id: id as u64})?,
+                None => write!(f, "{}", Block::Empty)?,
             }
         }
-        write!(f, "{s}")
+        Ok(())
+    }
+}
+
+// A file relocation made by the defragmenter. Fragmenting moves a file in
+// pieces, taking blocks off its end, so it logs one move per piece.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Move {
+    id: usize,
+    from: usize,
+    to: usize,
+    length: usize,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "move {} blocks of file {} from {} to {}", self.length, self.id, self.from, self.to)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DiskError {
+    Token(String), // something in a picture or listing that isn't a block
+    LeadingGap, // a dense map always starts with file 0
+    OutOfOrder{id: usize, position: usize}, // fragmented or reordered, so the map would renumber it
+    TooLong{position: usize, length: usize}, // a file or gap that doesn't fit in one digit
+    BadMove(Move), // the blocks to move aren't that file's, or where they're going isn't free
+}
+
+impl fmt::Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskError::Token(token) => write!(f, "{token:?} is not a block"),
+            DiskError::LeadingGap => write!(f, "the disk starts with free space"),
+            DiskError::OutOfOrder{id, position} => write!(f, "file {id} at {position} is out of order"),
+            DiskError::TooLong{position, length} => write!(f, "the {length} blocks at {position} don't fit in one digit"),
+            DiskError::BadMove(step) => write!(f, "can't {step}"),
+        }
     }
 }
 
@@ -119,46 +175,278 @@ impl Disk2 {
             position += length;
         });
         let size = files.iter().map(|file| file.length).sum::<usize>() + frees.iter().map(|free| free.length).sum::<usize>();
-        Disk2{ files, frees, _size: size }
+        Disk2{ files, frees, size }
     }
 
-    fn first_free(&self, file: &File) -> Option<usize> {
-        for i in 0..self.frees.len() {
-            if self.frees[i].length >= file.length && self.frees[i].position < file.position {
-                return Some(i)
+    // Rebuilds a disk from the ID in each block. Runs of the same ID become one
+    // file, so a file split into pieces comes back as several. An empty file has
+    // no blocks, so it doesn't come back at all.
+    fn from_blocks(blocks: &[Option<usize>]) -> Disk2 {
+        let mut files: Vec<File> = vec![];
+        for (position, block) in blocks.iter().enumerate() {
+            let Some(id) = *block else { continue };
+            match files.last_mut() {
+                Some(file) if file.id == id && file.position + file.length == position => file.length += 1,
+                _ => files.push(File { id, length: 1, position }),
             }
         }
-        None
-    }
-
-    fn defrag(&mut self) {
-        for i in (0..self.files.len()).rev() {
-            if let Some(j) = self.first_free(&self.files[i]) {
-                self.files[i].position = self.frees[j].position;
-                match self.files[i].length.cmp(&self.frees[j].length) {
-                    std::cmp::Ordering::Less => {
-                        self.frees[j].position += self.files[i].length;
-                        self.frees[j].length -= self.files[i].length;
-                    },
-                    std::cmp::Ordering::Equal => {
-                        self.frees.remove(j);
-                    },
-                    std::cmp::Ordering::Greater => unreachable!(),
+        let mut disk = Disk2 { files, frees: vec![], size: blocks.len() };
+        disk.frees = disk.gaps();
+        disk
+    }
+
+    // Reads back what Display writes.
+    #[allow(dead_code)]
+    fn from_picture(picture: &str) -> Result<Disk2, DiskError> {
+        let mut blocks = vec![];
+        let mut chars = picture.trim().chars();
+        while let Some(c) = chars.next() {
+            blocks.push(match c {
+                '.' => None,
+                '0'..='9' => Some(c.to_digit(10).unwrap() as usize),
+                '(' => {
+                    let mut id = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(')') => break,
+                            Some(c) => id.push(c),
+                            None => return Err(DiskError::Token(format!("({id}"))),
+                        }
+                    }
+                    Some(id.parse().map_err(|_| DiskError::Token(format!("({id})")))?)
                 }
-                continue
+                _ => return Err(DiskError::Token(c.to_string())),
+            });
+        }
+        Ok(Disk2::from_blocks(&blocks))
+    }
+
+    // One entry per block, the file ID or '.' if it's free.
+    #[allow(dead_code)]
+    fn listing(&self) -> String {
+        self.blocks().into_iter().map(|block| match block {
+            Some(id) => id.to_string(),
+            None => ".".to_string(),
+        }).collect::<Vec<_>>().join(" ")
+    }
+
+    #[allow(dead_code)]
+    fn from_listing(listing: &str) -> Result<Disk2, DiskError> {
+        let blocks = listing.split_whitespace().map(|token| match token {
+            "." => Ok(None),
+            _ => token.parse().map(Some).map_err(|_| DiskError::Token(token.to_string())),
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Disk2::from_blocks(&blocks))
+    }
+
+    fn blocks(&self) -> Vec<Option<usize>> {
+        let mut blocks = vec![None; self.size];
+        for file in self.files.iter() {
+            blocks[file.position..file.position+file.length].fill(Some(file.id));
+        }
+        blocks
+    }
+
+    // The dense map for this layout, if there is one: every file has to be in
+    // one piece and in ID order, and no file or gap can be longer than 9. Files
+    // with no gap between them get a 0. Empty files take no blocks, so they come
+    // from the file list and go in wherever their position falls between runs.
+    #[allow(dead_code)]
+    fn to_map(&self) -> Result<String, DiskError> {
+        fn push_file(map: &mut String, id: usize, length: usize, position: usize) -> Result<(), DiskError> {
+            if id != map.len().div_ceil(2) {
+                return Err(DiskError::OutOfOrder { id, position });
+            }
+            if map.len() % 2 == 1 {
+                map.push('0');
+            }
+            map.push(char::from_digit(length as u32, 10).unwrap());
+            Ok(())
+        }
+
+        let mut empty: Vec<(usize, usize)> = self.files.iter().filter(|file| file.length == 0).map(|file| (file.position, file.id)).collect();
+        empty.sort();
+        let mut empty = empty.into_iter().peekable();
+        let mut map = String::new();
+        let mut position = 0;
+        for (block, run) in self.blocks().chunk_by(|a, b| a == b).map(|run| (run[0], run.len())) {
+            while let Some((_, id)) = empty.next_if(|&(at, _)| at == position) {
+                push_file(&mut map, id, 0, position)?;
+            }
+            if run > 9 {
+                return Err(DiskError::TooLong { position, length: run });
+            }
+            match block {
+                Some(id) => push_file(&mut map, id, run, position)?,
+                None if map.is_empty() => return Err(DiskError::LeadingGap),
+                None => map.push(char::from_digit(run as u32, 10).unwrap()),
             }
+            position += run;
+        }
+        while let Some((_, id)) = empty.next_if(|&(at, _)| at == position) {
+            push_file(&mut map, id, 0, position)?;
+        }
+        // An empty file left over sits inside a run, which no map can say.
+        if let Some((position, id)) = empty.next() {
+            return Err(DiskError::OutOfOrder { id, position });
+        }
+        Ok(map)
+    }
+
+    // Applies a move log to this disk, checking each move as it goes.
+    fn replay(&mut self, moves: &[Move]) -> Result<(), DiskError> {
+        let mut blocks = self.blocks();
+        for &step in moves {
+            let (from, to) = (step.from..step.from+step.length, step.to..step.to+step.length);
+            if from.end > blocks.len() || to.end > blocks.len()
+                || blocks[from.clone()].iter().any(|&block| block != Some(step.id))
+                || blocks[to.clone()].iter().any(|block| block.is_some()) {
+                return Err(DiskError::BadMove(step));
+            }
+            blocks[from].fill(None);
+            blocks[to].fill(Some(step.id));
+        }
+        *self = Disk2::from_blocks(&blocks);
+        Ok(())
+    }
+
+    // The blocks that differ between two disks, as (position, ours, theirs).
+    fn diff(&self, other: &Disk2) -> Vec<(usize, Option<usize>, Option<usize>)> {
+        let (ours, theirs) = (self.blocks(), other.blocks());
+        (0..ours.len().max(theirs.len()))
+            .map(|i| (i, ours.get(i).copied().flatten(), theirs.get(i).copied().flatten()))
+            .filter(|(_, a, b)| a != b)
+            .collect()
+    }
+
+    // Moves each file, from the right, into a free span to its left. The space a
+    // file leaves behind is always to the right of every file we have left to
+    // move, so we never need to give it back. Returns every move it made.
+    fn defrag(&mut self, strategy: Strategy) -> Vec<Move> {
+        let mut spans = FreeSpans::new(&self.frees);
+        let mut files = vec![];
+        let mut moves = vec![];
+        for mut file in std::mem::take(&mut self.files).into_iter().rev() {
+            if strategy == Strategy::Fragmenting {
+                // Move blocks off the end of the file until there's no space left before it.
+                while let Some(span) = spans.find(strategy, 1, file.position) {
+                    let length = span.min(file.length);
+                    let position = spans.take(span, length);
+                    files.push(File { id: file.id, length, position });
+                    file.length -= length;
+                    moves.push(Move { id: file.id, from: file.position + file.length, to: position, length });
+                    if file.length == 0 {
+                        break
+                    }
+                }
+                if file.length > 0 {
+                    files.push(file);
+                }
+            } else {
+                if let Some(span) = spans.find(strategy, file.length, file.position) {
+                    let position = spans.take(span, file.length);
+                    moves.push(Move { id: file.id, from: file.position, to: position, length: file.length });
+                    file.position = position;
+                }
+                files.push(file);
+            }
+        }
+        files.reverse();
+        self.files = files;
+        self.frees = self.gaps();
+        moves
+    }
+
+    // The free spans, worked out from where the files are.
+    fn gaps(&self) -> Vec<Free> {
+        let mut extents: Vec<(usize, usize)> = self.files.iter().map(|file| (file.position, file.length)).collect();
+        extents.sort();
+        let mut frees = vec![];
+        let mut position = 0;
+        for (start, length) in extents.into_iter().chain([(self.size, 0)]) {
+            if start > position {
+                frees.push(Free { length: start - position, position });
+            }
+            position = position.max(start + length);
+        }
+        frees
+    }
+
+    fn report(&self) -> Report {
+        Report {
+            checksum: self.files.iter().map(|file| {
+                (file.position..file.position+file.length).sum::<usize>() * file.id
+            }).sum(),
+            free_spans: self.frees.len(),
+            largest_free_span: self.frees.iter().map(|free| free.length).max().unwrap_or(0),
         }
     }
 }
 
-fn part2(input: &str) -> usize {
+// How to choose where a file goes when we compact the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    FirstFit, // the leftmost span that fits the whole file (part 2)
+    BestFit, // the shortest span that fits, leftmost if there's a tie
+    WorstFit, // the longest span, leftmost if there's a tie
+    Fragmenting, // fill the leftmost free blocks, splitting the file if we have to (part 1)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Report {
+    checksum: usize,
+    free_spans: usize,
+    largest_free_span: usize,
+}
+
+fn compact(input: &str, strategy: Strategy) -> Report {
     let mut disk = Disk2::new(input);
+    disk.defrag(strategy);
+    disk.report()
+}
 
-    disk.defrag();
-    
-    disk.files.into_iter().map(|file| {
-        (file.position..file.position+file.length).sum::<usize>() * file.id
-    }).sum()
+// Scanning every free span for every file is O(files * frees). Instead we keep
+// a min-heap of positions for each span length, so the leftmost span that fits
+// a file is the smallest top of at most 9 heaps, and each move is O(log n).
+struct FreeSpans {
+    heaps: Vec<BinaryHeap<Reverse<usize>>>, // indexed by span length
+}
+
+impl FreeSpans {
+    fn new(frees: &[Free]) -> FreeSpans {
+        let longest = frees.iter().map(|free| free.length).max().unwrap_or(0);
+        let mut heaps = vec![BinaryHeap::new(); longest + 1];
+        for free in frees.iter().filter(|free| free.length > 0) {
+            heaps[free.length].push(Reverse(free.position));
+        }
+        FreeSpans { heaps }
+    }
+
+    // The length of the span this strategy would put this many blocks in. Each
+    // heap's top is its leftmost span, so that's the only one we need to look at.
+    fn find(&self, strategy: Strategy, length: usize, before: usize) -> Option<usize> {
+        let mut fits = (length..self.heaps.len())
+            .filter_map(|span| self.heaps[span].peek().map(|&Reverse(position)| (position, span)))
+            .filter(|&(position, _)| position < before);
+        match strategy {
+            Strategy::FirstFit | Strategy::Fragmenting => fits.min().map(|(_, span)| span),
+            Strategy::BestFit => fits.next().map(|(_, span)| span),
+            Strategy::WorstFit => fits.next_back().map(|(_, span)| span),
+        }
+    }
+
+    // Allocates the start of the leftmost span of this length, and returns its position.
+    fn take(&mut self, span: usize, length: usize) -> usize {
+        let Reverse(position) = self.heaps[span].pop().expect("a free span of this length");
+        if span > length {
+            self.heaps[span - length].push(Reverse(position + length));
+        }
+        position
+    }
+}
+
+fn part2(input: &str) -> usize {
+    compact(input, Strategy::FirstFit).checksum
 }
 
 #[cfg(test)]
@@ -175,6 +463,109 @@ mod day09 {
 
     #[test]
     fn test2() {
-        assert_eq!(part2(&SAMPLE), 2858)
-    }   
+        assert_eq!(part2(SAMPLE), 2858)
+    }
+
+    // The old quadratic first-fit, to check the heaps against.
+    fn naive(input: &str) -> usize {
+        let mut disk = Disk2::new(input);
+        for i in (0..disk.files.len()).rev() {
+            let file = &disk.files[i];
+            if let Some(j) = disk.frees.iter().position(|free| free.length >= file.length && free.position < file.position) {
+                disk.files[i].position = disk.frees[j].position;
+                disk.frees[j].position += disk.files[i].length;
+                disk.frees[j].length -= disk.files[i].length;
+            }
+        }
+        disk.files.into_iter().map(|file| {
+            (file.position..file.position+file.length).sum::<usize>() * file.id
+        }).sum()
+    }
+
+    #[test]
+    fn strategies() {
+        assert_eq!(compact(SAMPLE, Strategy::Fragmenting).checksum, 1928);
+        assert_eq!(compact(SAMPLE, Strategy::FirstFit), Report { checksum: 2858, free_spans: 6, largest_free_span: 5 });
+
+        // 0.....111..22 has room for file 2 in either gap.
+        let input = "15322";
+        let mut disk = Disk2::new(input);
+        disk.defrag(Strategy::BestFit);
+        assert_eq!(disk.to_string(), "0111.....22..");
+        assert_eq!(disk.report().free_spans, 2);
+        assert_eq!(disk.report().largest_free_span, 5);
+        let mut disk = Disk2::new(input);
+        disk.defrag(Strategy::WorstFit);
+        assert_eq!(disk.to_string(), "022111.......");
+        assert_eq!(disk.report().largest_free_span, 7);
+        assert_eq!(compact(input, Strategy::FirstFit), compact(input, Strategy::WorstFit));
+        assert_eq!(compact(input, Strategy::Fragmenting), compact(input, Strategy::WorstFit));
+    }
+
+    #[test]
+    fn images() {
+        let disk = Disk2::new(SAMPLE);
+        assert_eq!(disk.to_string(), "00...111...2...333.44.5555.6666.777.888899");
+        assert_eq!(disk.to_map(), Ok(SAMPLE.to_string()));
+        assert_eq!(Disk2::from_listing(&disk.listing()).unwrap().to_map(), Ok(SAMPLE.to_string()));
+
+        // Twelve files, so IDs 10 and 11 need brackets.
+        let input = "11111111111111111111113";
+        let disk = Disk2::new(input);
+        assert!(disk.to_string().ends_with(".9.(10).(11)(11)(11)"));
+        assert_eq!(disk.listing().rsplit(' ').next(), Some("11"));
+        let read = Disk2::from_picture(&disk.to_string()).unwrap();
+        assert_eq!(read.to_string(), disk.to_string());
+        assert_eq!(read.to_map(), Ok(input.to_string()));
+        assert_eq!(Disk2::from_picture("0(x)").err(), Some(DiskError::Token("(x)".to_string())));
+        assert_eq!(Disk2::from_picture("0(12").err(), Some(DiskError::Token("(12".to_string())));
+
+        let mut disk = Disk2::new(SAMPLE);
+        disk.defrag(Strategy::FirstFit);
+        assert_eq!(disk.to_map(), Err(DiskError::OutOfOrder { id: 9, position: 2 }));
+        assert_eq!(Disk2::from_picture("..0").unwrap().to_map(), Err(DiskError::LeadingGap));
+        assert_eq!(Disk2::from_picture("0..........1").unwrap().to_map(), Err(DiskError::TooLong { position: 1, length: 10 }));
+        assert_eq!(Disk2::from_picture("01.2").unwrap().to_map(), Ok("10111".to_string()));
+
+        // Empty files keep their place in the map, even first or last.
+        for input in ["10101", "0112", "12101", "11100"] {
+            assert_eq!(Disk2::new(input).to_map(), Ok(input.to_string()));
+        }
+    }
+
+    #[test]
+    fn move_log() {
+        for strategy in [Strategy::FirstFit, Strategy::BestFit, Strategy::WorstFit, Strategy::Fragmenting] {
+            let mut disk = Disk2::new(SAMPLE);
+            let moves = disk.defrag(strategy);
+            let mut replayed = Disk2::new(SAMPLE);
+            replayed.replay(&moves).unwrap();
+            assert_eq!(replayed.to_string(), disk.to_string());
+            assert_eq!(replayed.diff(&disk), vec![]);
+            let moved: usize = moves.iter().map(|step| step.length).sum();
+            assert_eq!(Disk2::new(SAMPLE).diff(&disk).len(), 2 * moved);
+        }
+
+        let mut disk = Disk2::new(SAMPLE);
+        let moves = disk.defrag(Strategy::FirstFit);
+        assert_eq!(moves[0], Move { id: 9, from: 40, to: 2, length: 2 });
+        assert_eq!(moves[0].to_string(), "move 2 blocks of file 9 from 40 to 2");
+        // Moving file 9 twice fails, because it's already gone.
+        let mut twice = Disk2::new(SAMPLE);
+        assert_eq!(twice.replay(&[moves[0], moves[0]]), Err(DiskError::BadMove(moves[0])));
+    }
+
+    #[test]
+    fn heaps() {
+        // A simple LCG is enough to make a big, messy disk map.
+        let mut x: u64 = 2024;
+        let input: String = (0..20_000).map(|i| {
+            x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let digit = (x >> 33) % 10;
+            char::from_digit(if i % 2 == 0 { digit.max(1) } else { digit } as u32, 10).unwrap()
+        }).collect();
+        assert_eq!(part2(&input), naive(&input));
+        let mut disk = parse(&input);
+        assert_eq!(compact(&input, Strategy::Fragmenting).checksum as u64, part1(&mut disk));
+    }
 }
\ No newline at end of file
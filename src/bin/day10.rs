@@ -1,4 +1,4 @@
-use std::{collections::{HashSet, VecDeque}, fs};
+use std::{collections::HashMap, fs, ops::RangeInclusive};
 
 use grid::*;
 
@@ -7,6 +7,12 @@ fn main() {
     let result = explore(&puzzle);
     println!("Part 1: {}", result.0);
     println!("Part 2: {}", result.1);
+    // Pass a number to see that many trails.
+    if let Some(limit) = std::env::args().nth(1).and_then(|arg| arg.parse().ok()) {
+        for trail in Topography::new(&parse(&puzzle)).trails(Some(limit)) {
+            println!("{trail:?}");
+        }
+    }
 }
 
 fn parse(input: &str) -> Grid<u32> {
@@ -17,52 +23,181 @@ fn parse(input: &str) -> Grid<u32> {
     grid
 }
 
-fn score(grid: &Grid<u32>, trailhead: (usize, usize)) -> (usize, usize) {
-    let mut frontier = VecDeque::new();
-    frontier.push_back(trailhead);
-    let mut nines = HashSet::new();
-    let mut rating = 0;
-    while !frontier.is_empty() {
-        if let Some(current) = frontier.pop_front() {
-            if grid[current] == 9 {
-                nines.insert(current);
-                rating += 1;
-            } else {
-                let (x,y) = current;
-                let mut neighbors = vec![];
-                // There's gotta be a better way. I don't love all this bounds checking.
-                if 0 < x {
-                    neighbors.push((x-1, y));
-                }
-                if x < grid.rows() -1 {
-                    neighbors.push((x+1, y));
-                }
-                if 0 < y {
-                    neighbors.push((x, y-1));
-                }
-                if y < grid.cols() - 1 {
-                    neighbors.push((x, y+1));
-                }
-                for (u,v) in neighbors {
-                    if grid[(u,v)] == grid[(x,y)] + 1{
-                        frontier.push_back((u,v));
-                    }
-                }
-            }
+// A set of peaks, one bit each.
+#[derive(Debug, Clone, Default)]
+struct Peaks(Vec<u64>);
+
+impl Peaks {
+    fn insert(&mut self, peak: usize) {
+        if self.0.len() <= peak / 64 {
+            self.0.resize(peak / 64 + 1, 0);
+        }
+        self.0[peak / 64] |= 1 << (peak % 64);
+    }
+
+    fn union_with(&mut self, other: &Peaks) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (word, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *word |= other;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    Four, // up, down, left and right
+    Eight, // diagonals too
+}
+
+// What counts as a trail. Every step has to change height in the same
+// direction, otherwise a trail could go round in circles forever.
+#[derive(Debug, Clone)]
+struct Rules {
+    climb: RangeInclusive<i32>, // how much higher each step can be; negative to descend
+    start: u32,
+    end: u32,
+    connectivity: Connectivity,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { climb: 1..=1, start: 0, end: 9, connectivity: Connectivity::Four }
+    }
+}
+
+fn neighbors(grid: &Grid<u32>, (x, y): (usize, usize), connectivity: Connectivity) -> impl Iterator<Item = (usize, usize)> {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    let offsets: &[(usize, usize)] = match connectivity {
+        Connectivity::Four => &[(0, 1), (2, 1), (1, 0), (1, 2)],
+        Connectivity::Eight => &[(0, 0), (0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1), (2, 2)],
+    };
+    offsets.iter().filter_map(move |(dx, dy)| {
+        let (u, v) = ((x + dx).checked_sub(1)?, (y + dy).checked_sub(1)?);
+        (u < rows && v < cols).then_some((u, v))
+    })
+}
+
+// For every cell, the peaks you can reach from it and how many distinct trails
+// get there. A cell's trails all go through a neighbour one step further along,
+// so working back from the peaks, one height at a time, each cell only has to
+// combine its neighbours' answers. With other rules a "peak" is any cell at the
+// end height, even if it's a valley.
+struct Topography {
+    grid: Grid<u32>,
+    rules: Rules,
+    reachable: Grid<Peaks>,
+    trails: Grid<usize>,
+    trailheads: Vec<(usize, usize)>,
+}
+
+impl Topography {
+    fn new(grid: &Grid<u32>) -> Topography {
+        Topography::with_rules(grid, Rules::default())
+    }
+
+    fn with_rules(grid: &Grid<u32>, rules: Rules) -> Topography {
+        assert!(!rules.climb.contains(&0) && rules.climb.start().signum() == rules.climb.end().signum(),
+            "trails have to keep going up, or keep going down");
+        let mut cells: Vec<(usize, usize)> = grid.indexed_iter().map(|(cell, _)| cell).collect();
+        // Cells nearest the end of a trail go first.
+        if *rules.climb.start() > 0 {
+            cells.sort_by_key(|&cell| std::cmp::Reverse(grid[cell]));
         } else {
-            unreachable!()
+            cells.sort_by_key(|&cell| grid[cell]);
+        }
+        let mut topography = Topography {
+            grid: grid.clone(),
+            reachable: Grid::new(grid.rows(), grid.cols()),
+            trails: Grid::new(grid.rows(), grid.cols()),
+            trailheads: grid.indexed_iter().filter(|(_, &height)| height == rules.start).map(|(cell, _)| cell).collect(),
+            rules,
+        };
+        let mut peaks = 0;
+        for cell in cells {
+            if grid[cell] == topography.rules.end {
+                topography.reachable[cell].insert(peaks);
+                topography.trails[cell] = 1;
+                peaks += 1;
+                continue
+            }
+            let (mut reachable, mut trails) = (Peaks::default(), 0);
+            for next in topography.steps(cell) {
+                reachable.union_with(&topography.reachable[next]);
+                trails += topography.trails[next];
+            }
+            topography.reachable[cell] = reachable;
+            topography.trails[cell] = trails;
+        }
+        topography
+    }
+
+    // Where the rules let you go from here.
+    fn steps(&self, cell: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        neighbors(&self.grid, cell, self.rules.connectivity)
+            .filter(move |&next| self.rules.climb.contains(&(self.grid[next] as i32 - self.grid[cell] as i32)))
+    }
+
+    // How many peaks each trailhead can reach.
+    fn scores(&self) -> HashMap<(usize, usize), usize> {
+        self.trailheads.iter().map(|&cell| (cell, self.reachable[cell].len())).collect()
+    }
+
+    // How many distinct trails start at each trailhead.
+    fn ratings(&self) -> HashMap<(usize, usize), usize> {
+        self.trailheads.iter().map(|&cell| (cell, self.trails[cell])).collect()
+    }
+
+    // Every trail, trailheads in reading order, stopping after `limit` if there is one.
+    fn trails(&self, limit: Option<usize>) -> Trails<'_> {
+        let stack = self.trailheads.iter().rev()
+            .filter(|&&cell| self.trails[cell] > 0)
+            .map(|&cell| (cell, 0))
+            .collect();
+        Trails { topography: self, stack, trail: vec![], remaining: limit }
+    }
+}
+
+// A depth-first walk that only steps onto cells with trails left to finish, so
+// it never wanders down a dead end.
+struct Trails<'a> {
+    topography: &'a Topography,
+    stack: Vec<((usize, usize), usize)>, // a cell, and how far along the trail it is
+    trail: Vec<(usize, usize)>,
+    remaining: Option<usize>,
+}
+
+impl Iterator for Trails<'_> {
+    type Item = Vec<(usize, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None
         }
+        let topography = self.topography;
+        while let Some((cell, depth)) = self.stack.pop() {
+            self.trail.truncate(depth);
+            self.trail.push(cell);
+            if topography.grid[cell] == topography.rules.end {
+                self.remaining = self.remaining.map(|remaining| remaining - 1);
+                return Some(self.trail.clone())
+            }
+            let steps: Vec<_> = topography.steps(cell).filter(|&next| topography.trails[next] > 0).collect();
+            self.stack.extend(steps.into_iter().rev().map(|next| (next, depth + 1)));
+        }
+        None
     }
-    (nines.len(), rating)
 }
 
 fn explore(input: &str) -> (usize, usize) {
-    let grid = parse(input);
-    grid.indexed_iter().filter_map(|((x,y), value)| match value {
-        0 => Some((x,y)),
-        _ => None
-    }).map(|trailhead| score(&grid, trailhead)).
-    fold((0,0), |(cumscore, cumrating), (score, rating)| (cumscore + score, cumrating + rating))
+    let topography = Topography::new(&parse(input));
+    (topography.scores().values().sum(), topography.ratings().values().sum())
 }
 
 #[cfg(test)]
@@ -123,6 +258,63 @@ const CHALLENGE: &str = "0123456789876543210123456789876543210
 1234567898987654321234567898987654321
 0123456789876543210123456789876543210";
 
+    // The old search from a single trailhead, to check the DP against.
+    fn bfs(grid: &Grid<u32>, trailhead: (usize, usize)) -> (usize, usize) {
+        let mut frontier = std::collections::VecDeque::from([trailhead]);
+        let mut nines = std::collections::HashSet::new();
+        let mut rating = 0;
+        while let Some(current) = frontier.pop_front() {
+            if grid[current] == 9 {
+                nines.insert(current);
+                rating += 1;
+            } else {
+                frontier.extend(neighbors(grid, current, Connectivity::Four).filter(|&next| grid[next] == grid[current] + 1));
+            }
+        }
+        (nines.len(), rating)
+    }
+
+    #[test]
+    fn maps() {
+        let grid = parse(SAMPLE2);
+        let topography = Topography::new(&grid);
+        let (scores, ratings) = (topography.scores(), topography.ratings());
+        let trailheads = &topography.trailheads;
+        assert_eq!(trailheads.iter().map(|cell| scores[cell]).collect::<Vec<_>>(), [5, 6, 5, 3, 1, 3, 5, 3, 5]);
+        assert_eq!(trailheads.iter().map(|cell| ratings[cell]).collect::<Vec<_>>(), [20, 24, 10, 4, 1, 4, 5, 8, 5]);
+        for trailhead in trailheads {
+            assert_eq!(bfs(&grid, *trailhead), (scores[trailhead], ratings[trailhead]));
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let grid = parse(SAMPLE2);
+        let descending = Topography::with_rules(&grid, Rules { climb: -1..=-1, start: 9, end: 0, ..Rules::default() });
+        assert_eq!(descending.scores().values().sum::<usize>(), 36);
+        assert_eq!(descending.ratings().values().sum::<usize>(), 81);
+
+        let grid = parse("01\n12");
+        let trails = |climb, connectivity| {
+            let rules = Rules { climb, end: 2, connectivity, ..Rules::default() };
+            Topography::with_rules(&grid, rules).trails(None).count()
+        };
+        assert_eq!(trails(1..=1, Connectivity::Eight), 2);
+        assert_eq!(trails(1..=2, Connectivity::Four), 2);
+        assert_eq!(trails(1..=2, Connectivity::Eight), 3);
+    }
+
+    #[test]
+    fn trails() {
+        let topography = Topography::new(&parse(SAMPLE2));
+        assert_eq!(topography.trails(None).count(), 81);
+        assert_eq!(topography.trails(Some(5)).count(), 5);
+        let first = topography.trails(None).next().unwrap();
+        assert_eq!(first.len(), 10);
+        assert_eq!(first[0], topography.trailheads[0]);
+        assert!(first.windows(2).all(|step| topography.grid[step[1]] == topography.grid[step[0]] + 1));
+    }
+
     #[test]
     fn test1() {
         assert_eq!(explore(SAMPLE1).0, 1)
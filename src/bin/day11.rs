@@ -1,34 +1,100 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use num::{pow::Pow, BigUint};
+use petgraph::{algo::tarjan_scc, dot::Dot, graphmap::DiGraphMap};
 
 fn main() {
     let puzzle = include_str!("../../puzzles/day11.txt");
     println!("Part 1: {}", part1(puzzle, 25));
     println!("Part 2: {}", part2(puzzle, 75));
+    let stones = parse(puzzle);
+    let transitions = Transitions::new(&stones, &Rules::default());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let number = |i: usize| args.get(i).map(|arg| arg.parse::<u64>().unwrap());
+    match args.first().map(String::as_str) {
+        // "matrix N PRIME" squares the transition matrix, "blinks N PRIME" goes
+        // through the recurrence, and "blinks N" gives the exact count.
+        Some("matrix") => println!("{} values, {} blinks: {} (mod {})", transitions.values.len(), args[1],
+            transitions.count_mod(&stones, number(1).unwrap(), number(2).unwrap()), args[2]),
+        Some("blinks") => match number(2) {
+            Some(prime) => println!("{} values, {} blinks: {} (mod {prime})", transitions.values.len(), args[1],
+                transitions.count_recurrence(&stones, number(1).unwrap(), prime)),
+            None => match transitions.count_big(&stones, number(1).unwrap()) {
+                Some(count) => println!("{} blinks: {count}", args[1]),
+                None => println!("{} blinks: too many to count exactly", args[1]),
+            },
+        },
+        // "orbit FILE" also writes out the transition graph.
+        Some("orbit") => {
+            let orbit = transitions.orbit();
+            println!("All {} values show up by blink {}, and {} of them keep coming back",
+                orbit.reachable, orbit.stable_at, orbit.attractor.len());
+            if let Some((value, _)) = orbit.first_seen.iter().filter(|&(_, &blink)| blink == orbit.stable_at).min() {
+                println!("{value} is one of the last to show up");
+            }
+            if let Some(path) = args.get(1) {
+                std::fs::write(path, transitions.dot()).unwrap();
+            }
+        }
+        _ => {}
+    }
 }
 
-fn part1(input: &str, blinks: u64) -> usize {
-    let mut stones: Vec<u64> = input.split_whitespace().map(|s| {
-        s.parse::<u64>().unwrap()
-    }).collect();
+// When a stone matches `when`, it turns into `then`'s stones.
+struct Rule {
+    when: Box<dyn Fn(u64) -> bool>,
+    then: Box<dyn Fn(u64) -> Vec<u64>>,
+}
+
+impl Rule {
+    fn new(when: impl Fn(u64) -> bool + 'static, then: impl Fn(u64) -> Vec<u64> + 'static) -> Rule {
+        Rule { when: Box::new(when), then: Box::new(then) }
+    }
+}
 
+// The first rule that matches a stone wins. A stone no rule matches stays as it is.
+struct Rules(Vec<Rule>);
+
+impl Rules {
+    fn blink(&self, stone: u64) -> Vec<u64> {
+        match self.0.iter().find(|rule| (rule.when)(stone)) {
+            Some(rule) => (rule.then)(stone),
+            None => vec![stone],
+        }
+    }
+}
+
+fn digits(x: u64) -> u32 {
+    x.checked_ilog10().unwrap_or(0) + 1
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules(vec![
+            Rule::new(|x| x == 0, |_| vec![1]),
+            Rule::new(|x| digits(x).is_multiple_of(2), |x| {
+                let half = 10_u64.pow(digits(x) / 2);
+                vec![x / half, x % half]
+            }),
+            Rule::new(|_| true, |x| vec![x * 2024]),
+        ])
+    }
+}
+
+fn parse(input: &str) -> Vec<u64> {
+    input.split_whitespace().map(|s| s.parse::<u64>().unwrap()).collect()
+}
+
+// Every stone, in order.
+fn simulate_list(mut stones: Vec<u64>, blinks: u64, rules: &Rules) -> Vec<u64> {
     for _ in 0..blinks {
-        let mut v = vec![];
-        for x in stones.into_iter() {
-            match x {
-                0 => v.push(1),
-                x if (x.ilog10() + 1) % 2 == 0 => {
-                    let digits = 1 + x.ilog10();
-                    let left = x / 10_u64.pow(digits/2);
-                    let right = x % 10_u64.pow(digits/2);
-                    v.push(left);
-                    v.push(right);
-                },
-                x => v.push(x * 2024)
-            }
-        };
-        stones = v;
+        stones = stones.into_iter().flat_map(|x| rules.blink(x)).collect();
     }
-    stones.len()
+    stones
+}
+
+fn part1(input: &str, blinks: u64) -> usize {
+    simulate_list(parse(input), blinks, &Rules::default()).len()
 }
 
 // I took inspiration from https://www.reddit.com/r/adventofcode/comments/1hbm0al/comment/m1hr2p6/
@@ -38,28 +104,357 @@ fn part1(input: &str, blinks: u64) -> usize {
 // you go to 1, to 2024, and then start branching. It looks like some branches do not
 // attract to some common value, so you end up with partially-overlapping subtrees that
 // you can't count until you have the whole thing.
-fn part2(input: &str, blinks: u8) -> u64 {
-    let mut stones: HashMap<u64, u64> = input.split_whitespace().map(|s| {
-        (s.parse::<u64>().unwrap(), 1)
-    }).collect();
-
+fn simulate_counts(stones: &[u64], blinks: u64, rules: &Rules) -> HashMap<u64, u64> {
+    let mut counts = HashMap::new();
+    for &stone in stones {
+        *counts.entry(stone).or_insert(0) += 1;
+    }
     for _ in 0..blinks {
         let mut y = HashMap::new();
-        stones.into_iter().for_each(|(k,v)| {
-            match k {
-                0 => *y.entry(1).or_insert(0) += v,
-                k if (k.ilog10() + 1) % 2 == 0 => {
-                    let digits = 1 + k.ilog10();
-                    *y.entry(k / 10_u64.pow(digits/2)).or_insert(0) += v;
-                    *y.entry(k % 10_u64.pow(digits/2)).or_insert(0) += v;
-                },
-                k => *y.entry(k * 2024).or_insert(0) += v,
-            };
+        counts.into_iter().for_each(|(k, v)| {
+            for stone in rules.blink(k) {
+                *y.entry(stone).or_insert(0) += v;
+            }
         });
-        stones = y;
+        counts = y;
+    }
+    counts
+}
+
+fn part2(input: &str, blinks: u8) -> u64 {
+    simulate_counts(&parse(input), blinks as u64, &Rules::default()).values().sum()
+}
+
+// Every value the starting stones can ever turn into, and what each one turns
+// into on a blink. That's a sparse transition matrix: most values only have one
+// or two successors. Once the set is closed, stone counts are a linear
+// recurrence, so we can jump straight to a huge number of blinks.
+struct Transitions {
+    values: Vec<u64>,
+    index: HashMap<u64, usize>,
+    next: Vec<Vec<usize>>, // a successor appears twice if a stone splits into two of it
+    first_seen: Vec<u64>, // the first blink each value shows up on
+}
+
+impl Transitions {
+    // Only finishes if the rules keep the values in a finite set, like the
+    // default ones do.
+    fn new(stones: &[u64], rules: &Rules) -> Transitions {
+        let mut transitions = Transitions { values: vec![], index: HashMap::new(), next: vec![], first_seen: vec![] };
+        for &stone in stones {
+            transitions.insert(stone, 0);
+        }
+        // Values go in breadth first, so the first time we see one is the
+        // first blink it can appear on.
+        let mut i = 0;
+        while i < transitions.values.len() {
+            let blink = transitions.first_seen[i] + 1;
+            let next = rules.blink(transitions.values[i]).into_iter().map(|stone| transitions.insert(stone, blink)).collect();
+            transitions.next.push(next);
+            i += 1;
+        }
+        transitions
+    }
+
+    fn insert(&mut self, value: u64, blink: u64) -> usize {
+        *self.index.entry(value).or_insert_with(|| {
+            self.values.push(value);
+            self.first_seen.push(blink);
+            self.values.len() - 1
+        })
+    }
+
+    // How many stones there are after each blink, modulo a prime, for as many
+    // blinks as it takes to pin down the recurrence.
+    fn totals(&self, stones: &[u64], prime: u64, blinks: usize) -> Vec<u64> {
+        let mut counts = vec![0; self.values.len()];
+        for stone in stones {
+            counts[self.index[stone]] += 1;
+        }
+        let mut totals = vec![];
+        for _ in 0..blinks {
+            totals.push(counts.iter().fold(0, |total, count| add_mod(total, *count, prime)));
+            let mut next = vec![0; counts.len()];
+            for (i, &count) in counts.iter().enumerate() {
+                for &j in self.next[i].iter() {
+                    next[j] = add_mod(next[j], count, prime);
+                }
+            }
+            counts = next;
+        }
+        totals
+    }
+
+    // Each value's row lists how many stones of each value it turns into.
+    fn matrix(&self, prime: u64) -> Matrix {
+        Matrix(self.next.iter().map(|next| {
+            let mut row: Vec<(usize, u64)> = vec![];
+            for &j in next {
+                match row.iter_mut().find(|(column, _)| *column == j) {
+                    Some((_, count)) => *count = add_mod(*count, 1, prime),
+                    None => row.push((j, 1 % prime)),
+                }
+            }
+            row
+        }).collect())
+    }
+
+    // Stones after this many blinks, modulo a prime, by repeated squaring of
+    // the transition matrix. Its powers fill in after a few squarings, and
+    // from then on each one costs values^3, which is fine for a few hundred
+    // values but takes hours for the ~4000 a real input reaches.
+    fn count_mod(&self, stones: &[u64], blinks: u64, prime: u64) -> u64 {
+        let mut counts = vec![0; self.values.len()];
+        for stone in stones {
+            counts[self.index[stone]] = add_mod(counts[self.index[stone]], 1, prime);
+        }
+        let (mut power, mut n) = (self.matrix(prime), blinks);
+        while n > 0 {
+            if n & 1 == 1 {
+                counts = power.apply(&counts, prime);
+            }
+            n >>= 1;
+            if n > 0 {
+                power = power.mul(&power, prime);
+            }
+        }
+        counts.iter().fold(0, |total, count| add_mod(total, *count, prime))
+    }
+
+    // The same thing, squaring x instead of the matrix. The recurrence can't be
+    // longer than the number of values, so twice that many terms is enough to
+    // find it, and then x^blinks modulo its characteristic polynomial says how
+    // to combine the first few totals into the one we want. That polynomial
+    // also sends the matrix to zero, so this is the matrix power with each
+    // squaring down to values^2.
+    fn count_recurrence(&self, stones: &[u64], blinks: u64, prime: u64) -> u64 {
+        let totals = self.totals(stones, prime, 2 * self.values.len() + 1);
+        if blinks < totals.len() as u64 {
+            return totals[blinks as usize];
+        }
+        let recurrence = berlekamp_massey(&totals, prime);
+        let weights = power_of_x(blinks, &recurrence, prime);
+        weights.iter().zip(totals.iter()).fold(0, |total, (&weight, &term)| add_mod(total, mul_mod(weight, term, prime), prime))
+    }
+
+    // The exact count, rebuilt from enough primes to cover it. Each blink
+    // multiplies the stones by at most the most any value splits into, which
+    // for the default rules is about one prime per 60 blinks. None if that
+    // bound runs past a few megabytes, since we'd never get through the primes.
+    fn count_big(&self, stones: &[u64], blinks: u64) -> Option<BigUint> {
+        let branching = self.next.iter().map(Vec::len).max().unwrap_or(0);
+        let bits = blinks.checked_mul((usize::BITS - branching.saturating_sub(1).leading_zeros()) as u64)?;
+        if bits > 1 << 24 {
+            return None
+        }
+        let bound = BigUint::from(stones.len()) * Pow::pow(BigUint::from(branching), blinks);
+        let (mut count, mut modulus) = (BigUint::ZERO, BigUint::from(1u32));
+        for prime in primes_below(1 << 61) {
+            if modulus > bound {
+                break
+            }
+            // Garner's step: fix up the count so it's also right modulo this prime.
+            let residue = self.count_recurrence(stones, blinks, prime);
+            let current = (&count % prime).try_into().unwrap_or(0u64);
+            let inverse = pow_mod((&modulus % prime).try_into().unwrap_or(0u64), prime - 2, prime);
+            let step = mul_mod(add_mod(residue, prime - current, prime), inverse, prime);
+            count += &modulus * step;
+            modulus *= prime;
+        }
+        Some(count)
+    }
+}
+
+// What the starting stones settle into. Every value shows up by `stable_at`,
+// but only the attractor's values keep showing up forever after.
+struct Orbit {
+    reachable: usize,
+    first_seen: HashMap<u64, u64>,
+    stable_at: u64,
+    attractor: HashSet<u64>,
+}
+
+impl Transitions {
+    fn graph(&self) -> DiGraphMap<u64, u32> {
+        let mut graph = DiGraphMap::new();
+        for (i, next) in self.next.iter().enumerate() {
+            graph.add_node(self.values[i]);
+            for &j in next {
+                let (from, to) = (self.values[i], self.values[j]);
+                match graph.edge_weight_mut(from, to) {
+                    Some(weight) => *weight += 1,
+                    None => { graph.add_edge(from, to, 1); }
+                }
+            }
+        }
+        graph
+    }
+
+    // Edges are labelled with how many stones a blink turns one into.
+    fn dot(&self) -> String {
+        format!("{}", Dot::new(&self.graph()))
+    }
+
+    // A value keeps coming back if it's on a cycle, or downstream of one.
+    fn orbit(&self) -> Orbit {
+        let graph = self.graph();
+        let mut attractor = HashSet::new();
+        let mut frontier: Vec<u64> = tarjan_scc(&graph).into_iter()
+            .filter(|scc| scc.len() > 1 || graph.contains_edge(scc[0], scc[0]))
+            .flatten()
+            .collect();
+        while let Some(value) = frontier.pop() {
+            if attractor.insert(value) {
+                frontier.extend(graph.neighbors(value));
+            }
+        }
+        Orbit {
+            reachable: self.values.len(),
+            first_seen: self.values.iter().copied().zip(self.first_seen.iter().copied()).collect(),
+            stable_at: self.first_seen.iter().copied().max().unwrap_or(0),
+            attractor,
+        }
+    }
+}
+
+// A sparse square matrix modulo a prime, as the nonzero entries of each row.
+struct Matrix(Vec<Vec<(usize, u64)>>);
+
+impl Matrix {
+    fn mul(&self, other: &Matrix, prime: u64) -> Matrix {
+        let mut sums = vec![0; other.0.len()];
+        Matrix(self.0.iter().map(|row| {
+            for &(k, a) in row {
+                for &(j, b) in other.0[k].iter() {
+                    sums[j] = add_mod(sums[j], mul_mod(a, b, prime), prime);
+                }
+            }
+            sums.iter_mut().enumerate().filter(|(_, sum)| **sum != 0).map(|(j, sum)| (j, std::mem::take(sum))).collect()
+        }).collect())
+    }
+
+    // A row vector times this matrix.
+    fn apply(&self, vector: &[u64], prime: u64) -> Vec<u64> {
+        let mut result = vec![0; vector.len()];
+        for (row, &x) in self.0.iter().zip(vector) {
+            for &(j, a) in row {
+                result[j] = add_mod(result[j], mul_mod(x, a, prime), prime);
+            }
+        }
+        result
+    }
+}
+
+// Both in u128, so a prime near 2^64 can't overflow.
+fn add_mod(a: u64, b: u64, prime: u64) -> u64 {
+    ((a as u128 + b as u128) % prime as u128) as u64
+}
+
+fn mul_mod(a: u64, b: u64, prime: u64) -> u64 {
+    (a as u128 * b as u128 % prime as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, prime: u64) -> u64 {
+    let mut result = 1 % prime;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, prime);
+        }
+        base = mul_mod(base, base, prime);
+        exponent >>= 1;
+    }
+    result
+}
+
+// The shortest c such that s[n] = c[0]s[n-1] + c[1]s[n-2] + ... for every n.
+fn berlekamp_massey(sequence: &[u64], prime: u64) -> Vec<u64> {
+    let (mut current, mut previous) = (vec![1], vec![1]);
+    let (mut length, mut shift, mut last) = (0, 1, 1);
+    for n in 0..sequence.len() {
+        let discrepancy = (0..=length).fold(0, |d, i| add_mod(d, mul_mod(current[i], sequence[n - i], prime), prime));
+        if discrepancy == 0 {
+            shift += 1;
+            continue
+        }
+        let scale = mul_mod(discrepancy, pow_mod(last, prime - 2, prime), prime);
+        let saved = current.clone();
+        if current.len() < previous.len() + shift {
+            current.resize(previous.len() + shift, 0);
+        }
+        for (i, &p) in previous.iter().enumerate() {
+            current[i + shift] = add_mod(current[i + shift], prime - mul_mod(scale, p, prime), prime);
+        }
+        if 2 * length <= n {
+            length = n + 1 - length;
+            (previous, last, shift) = (saved, discrepancy, 1);
+        } else {
+            shift += 1;
+        }
+    }
+    current.resize(length + 1, 0);
+    current[1..].iter().map(|&c| (prime - c) % prime).collect()
+}
+
+// x^n modulo x^L - c[0]x^(L-1) - ... - c[L-1], by repeated squaring. Its
+// coefficients are the weights of s[0..L] in s[n].
+fn power_of_x(n: u64, recurrence: &[u64], prime: u64) -> Vec<u64> {
+    let length = recurrence.len();
+    // No recurrence means every term is 0, and there's nothing to weigh.
+    if length == 0 {
+        return vec![]
+    }
+    // Folds the terms from x^L upwards back down into the first L.
+    let reduce = |mut poly: Vec<u64>| {
+        for i in (length..poly.len()).rev() {
+            let top = poly[i];
+            for (j, &c) in recurrence.iter().enumerate() {
+                poly[i - j - 1] = add_mod(poly[i - j - 1], mul_mod(top, c, prime), prime);
+            }
+        }
+        poly.truncate(length);
+        poly
+    };
+    let mut result = vec![1 % prime];
+    for bit in (0..u64::BITS - n.leading_zeros()).rev() {
+        let mut square = vec![0; 2 * result.len() - 1];
+        for (i, &a) in result.iter().enumerate() {
+            for (j, &b) in result.iter().enumerate() {
+                square[i + j] = add_mod(square[i + j], mul_mod(a, b, prime), prime);
+            }
+        }
+        result = reduce(square);
+        if n >> bit & 1 == 1 {
+            result.insert(0, 0);
+            result = reduce(result);
+        }
     }
+    result.resize(length, 0);
+    result
+}
 
-    stones.values().sum()
+// Descending, by Miller-Rabin. These bases are enough for any u64.
+fn primes_below(limit: u64) -> impl Iterator<Item = u64> {
+    let is_prime = |n: u64| {
+        if n < 2 || n.is_multiple_of(2) {
+            return n == 2
+        }
+        let (mut d, mut r) = (n - 1, 0);
+        while d.is_multiple_of(2) {
+            d /= 2;
+            r += 1;
+        }
+        [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37].iter().all(|&a| {
+            let mut x = pow_mod(a % n, d, n);
+            if a % n == 0 || x == 1 || x == n - 1 {
+                return true
+            }
+            (1..r).any(|_| {
+                x = mul_mod(x, x, n);
+                x == n - 1
+            })
+        })
+    };
+    (2..limit).rev().filter(move |&n| is_prime(n))
 }
 
 #[cfg(test)]
@@ -89,4 +484,121 @@ mod day11 {
     fn test4() {
         assert_eq!(part2(SAMPLE, 25), 55312);
     } 
+
+    #[test]
+    fn custom_rules() {
+        let collatz = || Rules(vec![
+            Rule::new(|x| x.is_multiple_of(2), |x| vec![x / 2]),
+            Rule::new(|x| x > 1, |x| vec![3 * x + 1]),
+        ]);
+        assert_eq!(simulate_list(vec![6, 7], 8, &collatz()), [1, 40]);
+        assert_eq!(simulate_counts(&[6, 7], 8, &collatz()), HashMap::from([(1, 1), (40, 1)]));
+
+        // Stones that count down to zero and then vanish.
+        let countdown = Rules(vec![
+            Rule::new(|x| x == 0, |_| vec![]),
+            Rule::new(|_| true, |x| vec![x - 1, x - 1]),
+        ]);
+        assert_eq!(simulate_list(vec![3, 5], 4, &countdown), [1; 16]);
+        assert_eq!(simulate_counts(&[3, 5], 4, &countdown), HashMap::from([(1, 16)]));
+    }
+
+    #[test]
+    fn matrix() {
+        let stones = parse(SAMPLE);
+        let transitions = Transitions::new(&stones, &Rules::default());
+        assert_eq!(transitions.values.len(), 76);
+        let prime = 1_000_000_007;
+        assert_eq!(transitions.count_mod(&stones, 6, prime), 22);
+        assert_eq!(transitions.count_mod(&stones, 75, prime), part2(SAMPLE, 75) % prime);
+        assert_eq!(transitions.count_recurrence(&stones, 75, prime), part2(SAMPLE, 75) % prime);
+        let totals = transitions.totals(&stones, 101, 501);
+        assert_eq!(transitions.count_mod(&stones, 500, 101), totals[500]);
+        assert_eq!(transitions.count_recurrence(&stones, 500, 101), totals[500]);
+        assert_eq!(transitions.count_mod(&stones, 1_000_000_000_000, prime),
+            transitions.count_recurrence(&stones, 1_000_000_000_000, prime));
+
+        // Totals that are all 0 have no recurrence to speak of.
+        let transitions = Transitions::new(&[0, 0], &Rules::default());
+        assert_eq!(transitions.count_recurrence(&[0, 0], 1_000_000, 2), 0);
+        assert_eq!(transitions.count_mod(&[0, 0], 1_000_000, 2), 0);
+        let transitions = Transitions::new(&[], &Rules::default());
+        assert_eq!(transitions.count_recurrence(&[], 1_000_000, prime), 0);
+        assert_eq!(transitions.count_mod(&[], 1_000_000, prime), 0);
+        assert_eq!(transitions.count_big(&[], 1_000), Some(BigUint::ZERO));
+
+        let transitions = Transitions::new(&stones, &Rules::default());
+        assert_eq!(transitions.count_big(&stones, 75), Some(BigUint::from(part2(SAMPLE, 75))));
+        assert_eq!(transitions.count_big(&stones, 1_000_000_000_000), None);
+
+        // Residues near 2^64 still add up.
+        let big_prime = 18_446_744_073_709_551_557;
+        assert_eq!(transitions.count_mod(&stones, 75, big_prime), part2(SAMPLE, 75));
+        assert_eq!(transitions.count_recurrence(&stones, 75, big_prime), part2(SAMPLE, 75));
+        assert_eq!(transitions.count_mod(&stones, 500, big_prime), transitions.count_recurrence(&stones, 500, big_prime));
+
+        // Counted the slow way, there's no modulus to hide behind.
+        let mut counts = vec![BigUint::ZERO; transitions.values.len()];
+        stones.iter().for_each(|stone| counts[transitions.index[stone]] += 1u32);
+        for _ in 0..300 {
+            let mut next = vec![BigUint::ZERO; counts.len()];
+            for (i, count) in counts.iter().enumerate() {
+                transitions.next[i].iter().for_each(|&j| next[j] += count);
+            }
+            counts = next;
+        }
+        assert_eq!(transitions.count_big(&stones, 300), Some(counts.iter().sum::<BigUint>()));
+
+        // Stones that split three ways need a bigger bound than the default rules.
+        let triple = Rules(vec![Rule::new(|_| true, |x| vec![x, x, x])]);
+        let transitions = Transitions::new(&[7], &triple);
+        assert_eq!(transitions.count_big(&[7], 200), Some(Pow::pow(BigUint::from(3u32), 200u32)));
+    }
+
+    #[test]
+    fn orbits() {
+        let orbit = Transitions::new(&[0], &Rules::default()).orbit();
+        assert_eq!(orbit.reachable, 54);
+        assert_eq!(orbit.attractor.len(), 54);
+        assert_eq!(orbit.first_seen[&2024], 2);
+        assert_eq!(orbit.first_seen[&4], 4);
+        assert!(orbit.first_seen.values().all(|&blink| blink <= orbit.stable_at));
+        assert!(orbit.first_seen.values().any(|&blink| blink == orbit.stable_at));
+
+        let orbit = Transitions::new(&parse(SAMPLE), &Rules::default()).orbit();
+        assert_eq!(orbit.first_seen[&125], 0);
+        assert!(!orbit.attractor.contains(&125));
+        let dot = Transitions::new(&[0], &Rules::default()).dot();
+        assert!(dot.starts_with("digraph {"));
+        // 93 successors, but one value splits into two of the same stone.
+        assert_eq!(dot.matches("->").count(), 92);
+        assert_eq!(dot.lines().filter(|line| line.contains("->") && line.contains("label = \"2\"")).count(), 1);
+
+        // The post says everything converges to 3947 values. None of the sets
+        // below come to that. The attractor, the values that keep coming back,
+        // is 3811 for inputs like the puzzle's and takes in the loop through 0
+        // as well, but bigger stones can reach more cycles: stones 0 to 99999
+        // have 3826. Counting the distinct values after some number of blinks
+        // instead depends on when you stop, since big stones take a while to
+        // fall in: those same stones have 3818 values after 75 blinks.
+        let inputs = [
+            vec![554735, 45401, 8434, 0, 188, 7487525, 77, 7],
+            vec![125, 17, 4022724, 951333, 0, 21633, 5857, 97, 702, 6],
+            vec![3279, 998884, 1832781, 517, 8, 18864, 28, 0],
+        ];
+        let mut attractors = inputs.iter().map(|stones| Transitions::new(stones, &Rules::default()).orbit().attractor);
+        let first = attractors.next().unwrap();
+        assert_eq!(first.len(), 3811);
+        assert!(attractors.all(|attractor| attractor == first));
+        let zero = Transitions::new(&[0], &Rules::default()).orbit().attractor;
+        assert!(zero.is_subset(&first));
+        // Stones 0 to 999 have settled by blink 75, onto exactly that set.
+        let settled = simulate_counts(&(0..1000).collect::<Vec<u64>>(), 75, &Rules::default());
+        assert_eq!(settled.into_keys().collect::<HashSet<u64>>(), first);
+        let stones: Vec<u64> = (0..100_000).collect();
+        assert_eq!(simulate_counts(&stones, 75, &Rules::default()).len(), 3818);
+        let attractor = Transitions::new(&stones, &Rules::default()).orbit().attractor;
+        assert_eq!(attractor.len(), 3826);
+        assert!(first.is_subset(&attractor));
+    }
 }
\ No newline at end of file
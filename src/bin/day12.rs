@@ -1,6 +1,6 @@
 use grid::*;
 use itertools::Itertools;
-use std::collections::{HashSet, VecDeque};
+use std::{collections::{BTreeMap, HashMap, HashSet}, fmt};
 
 fn main() {
     let puzzle = include_str!("../../puzzles/day12.txt");
@@ -8,181 +8,478 @@ fn main() {
     let (part1, part2) = solve(&grid);
     println!("Part 1: {}", part1);
     println!("Part 2: {}", part2);
+    // Pass "bill" to see what each region costs.
+    if std::env::args().nth(1).as_deref() == Some("bill") {
+        println!("{}", bill(&grid, &SharedFences { policy: BySides, discount: 50 }));
+        return
+    }
+    // Pass a .svg or .geojson file name to draw the garden.
+    if let Some(path) = std::env::args().nth(1) {
+        let garden = Garden::new(&grid);
+        let contents = if path.ends_with(".svg") { garden.svg() } else { garden.geojson() };
+        std::fs::write(path, contents).unwrap();
+    }
 }
 
 fn solve(grid: &Grid<char>) -> (usize, usize) {
-    let mut explored: HashSet<(usize, usize)> = HashSet::new();
-    let mut regions = vec![];
-
-    for (row, col) in (0..grid.rows()).cartesian_product(0..grid.cols()) {
-        if !explored.contains(&(row, col)) {
-            let region = explore(grid, row, col);
-            explored.extend(&region);
-            regions.push(region);
+    (bill(grid, &ByPerimeter).total(), bill(grid, &BySides).total())
+}
+
+// How a region's fence is priced. The wrappers each change one thing about
+// the policy inside them, so they stack.
+trait PricingPolicy {
+    // How much fence to charge for.
+    fn fence(&self, region: &Region) -> usize;
+
+    fn unit_price(&self, _plant: char) -> usize {
+        1
+    }
+
+    // Percent off the part of the fence a region shares with its neighbours.
+    fn shared_discount(&self) -> usize {
+        0
+    }
+
+    fn connectivity(&self) -> Connectivity {
+        Connectivity::Four
+    }
+}
+
+struct ByPerimeter; // part 1
+
+impl PricingPolicy for ByPerimeter {
+    fn fence(&self, region: &Region) -> usize {
+        region.perimeter
+    }
+}
+
+struct BySides; // part 2
+
+impl PricingPolicy for BySides {
+    fn fence(&self, region: &Region) -> usize {
+        region.sides
+    }
+}
+
+// Some plants cost more to fence in. Anything not listed costs 1.
+#[allow(dead_code)]
+struct PerPlant<P> {
+    policy: P,
+    prices: HashMap<char, usize>,
+}
+
+impl<P: PricingPolicy> PricingPolicy for PerPlant<P> {
+    fn fence(&self, region: &Region) -> usize {
+        self.policy.fence(region)
+    }
+
+    fn unit_price(&self, plant: char) -> usize {
+        self.prices.get(&plant).copied().unwrap_or(1) * self.policy.unit_price(plant)
+    }
+
+    fn shared_discount(&self) -> usize {
+        self.policy.shared_discount()
+    }
+
+    fn connectivity(&self) -> Connectivity {
+        self.policy.connectivity()
+    }
+}
+
+// Neighbours split the cost of the fence between them.
+struct SharedFences<P> {
+    policy: P,
+    discount: usize, // percent
+}
+
+impl<P: PricingPolicy> PricingPolicy for SharedFences<P> {
+    fn fence(&self, region: &Region) -> usize {
+        self.policy.fence(region)
+    }
+
+    fn unit_price(&self, plant: char) -> usize {
+        self.policy.unit_price(plant)
+    }
+
+    fn shared_discount(&self) -> usize {
+        self.discount
+    }
+
+    fn connectivity(&self) -> Connectivity {
+        self.policy.connectivity()
+    }
+}
+
+// Plants that only touch at a corner count as one region.
+#[allow(dead_code)]
+struct Diagonal<P>(P);
+
+impl<P: PricingPolicy> PricingPolicy for Diagonal<P> {
+    fn fence(&self, region: &Region) -> usize {
+        self.0.fence(region)
+    }
+
+    fn unit_price(&self, plant: char) -> usize {
+        self.0.unit_price(plant)
+    }
+
+    fn shared_discount(&self) -> usize {
+        self.0.shared_discount()
+    }
+
+    fn connectivity(&self) -> Connectivity {
+        Connectivity::Eight
+    }
+}
+
+// One line of the bill. The discount is taken off in proportion to how much of
+// the fence is shared, whatever the fence is measured in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    label: usize,
+    plant: char,
+    area: usize,
+    fence: usize,
+    unit_price: usize,
+    discount: usize,
+    price: usize,
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4} {}: area {} x fence {} x {}", self.label, self.plant, self.area, self.fence, self.unit_price)?;
+        if self.discount > 0 {
+            write!(f, " - {}", self.discount)?;
         }
+        write!(f, " = {}", self.price)
     }
+}
 
-    let part1 = regions
-        .iter()
-        .map(|region| area(region) * perimeter(region))
-        .sum();
-    let part2 = regions
-        .iter()
-        .map(|region| area(region) * edges(region))
-        .sum();
+struct Bill(Vec<Item>);
 
-    (part1, part2)
+impl Bill {
+    fn total(&self) -> usize {
+        self.0.iter().map(|item| item.price).sum()
+    }
 }
 
-fn explore(grid: &Grid<char>, row: usize, col: usize) -> HashSet<(usize, usize)> {
-    let mut region = HashSet::new();
-    let mut discovered = HashSet::new();
-    let mut frontier = VecDeque::new();
-
-    discovered.insert((row, col));
-    frontier.push_back((row, col));
-    let label = grid[(row, col)];
-
-    while let Some((u, v)) = frontier.pop_front() {
-        region.insert((u, v));
-        if u > 0
-            && grid[(u - 1, v)] == label
-            && !discovered.contains(&(u - 1, v))
-            && !region.contains(&(u - 1, v))
-        {
-            discovered.insert((u - 1, v));
-            frontier.push_back((u - 1, v));
+impl fmt::Display for Bill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in self.0.iter() {
+            writeln!(f, "{item}")?;
         }
-        if v > 0
-            && grid[(u, v - 1)] == label
-            && !discovered.contains(&(u, v - 1))
-            && !region.contains(&(u, v - 1))
-        {
-            discovered.insert((u, v - 1));
-            frontier.push_back((u, v - 1));
+        write!(f, "Total: {}", self.total())
+    }
+}
+
+fn bill(grid: &Grid<char>, policy: &dyn PricingPolicy) -> Bill {
+    let garden = Garden::with_connectivity(grid, policy.connectivity());
+    Bill(garden.regions.iter().enumerate().map(|(label, region)| {
+        let (fence, unit_price) = (policy.fence(region), policy.unit_price(region.plant));
+        let full = region.area * fence * unit_price;
+        let discount = full * region.shared * policy.shared_discount() / (100 * region.perimeter);
+        Item { label, plant: region.plant, area: region.area, fence, unit_price, discount, price: full - discount }
+    }).collect())
+}
+
+// Disjoint sets of cells, with path halving and union by size.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> UnionFind {
+        UnionFind { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
         }
-        if u < grid.rows() - 1
-            && grid[(u + 1, v)] == label
-            && !discovered.contains(&(u + 1, v))
-            && !region.contains(&(u + 1, v))
-        {
-            discovered.insert((u + 1, v));
-            frontier.push_back((u + 1, v));
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return
         }
-        if v < grid.cols() - 1
-            && grid[(u, v + 1)] == label
-            && !discovered.contains(&(u, v + 1))
-            && !region.contains(&(u, v + 1))
-        {
-            discovered.insert((u, v + 1));
-            frontier.push_back((u, v + 1));
+        if self.size[a] < self.size[b] {
+            (a, b) = (b, a);
         }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    plant: char,
+    area: usize,
+    perimeter: usize,
+    sides: usize, // the same as the number of corners
+    shared: usize, // how much of the perimeter is next to another region
+}
 
-    region
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    Four, // up, down, left and right
+    Eight, // diagonals too
 }
 
-fn area(region: &HashSet<(usize, usize)>) -> usize {
-    region.len()
+// Every cell labelled with its region, numbered in reading order of each
+// region's first cell.
+struct Garden {
+    labels: Grid<usize>,
+    regions: Vec<Region>,
+    connectivity: Connectivity,
 }
 
-fn perimeter(region: &HashSet<(usize, usize)>) -> usize {
-    let mut perimeter = 0;
-    for (x, y) in region {
-        // sometimes the strong typing is annoying.
-        let x = *x as i32;
-        let y = *y as i32;
-        // OK WTH...there's some impossible bug here where if you change (-1, 0) to (1, 0)
-        // you'll still get the right result, but it fails if you take (-1, 0) out completely. IDK.
-        for (dx, dy) in [(1, 0), (-1, 0), (0, -1), (0, 1)] {
-            if !region.contains(&((x + dx) as usize, (y + dy) as usize)) {
-                perimeter += 1
+impl Garden {
+    fn new(grid: &Grid<char>) -> Garden {
+        Garden::with_connectivity(grid, Connectivity::Four)
+    }
+
+    fn with_connectivity(grid: &Grid<char>, connectivity: Connectivity) -> Garden {
+        let (rows, cols) = (grid.rows(), grid.cols());
+        // Joining each cell to the cells above and to the left (and the two
+        // diagonals above it) is enough to connect every region in one pass.
+        let mut sets = UnionFind::new(rows * cols);
+        let earlier: &[(usize, usize)] = match connectivity {
+            Connectivity::Four => &[(0, 1), (1, 0)],
+            Connectivity::Eight => &[(0, 1), (1, 0), (0, 0), (0, 2)],
+        };
+        for (row, col) in (0..rows).cartesian_product(0..cols) {
+            for &(dr, dc) in earlier {
+                // Offsets are shifted by (1, 1) so they stay unsigned.
+                let (Some(r), Some(c)) = ((row + dr).checked_sub(1), (col + dc).checked_sub(1)) else { continue };
+                if c < cols && grid[(r, c)] == grid[(row, col)] {
+                    sets.union(row * cols + col, r * cols + c);
+                }
             }
         }
-    }
-    perimeter
-}
 
-#[derive(PartialEq, Eq)]
-enum Side {
-    Top,
-    Bottom,
-    Left,
-    Right,
-}
+        let mut labels = Grid::new(rows, cols);
+        let mut regions: Vec<Region> = vec![];
+        let mut roots = HashMap::new();
+        for (row, col) in (0..rows).cartesian_product(0..cols) {
+            let root = sets.find(row * cols + col);
+            let label = *roots.entry(root).or_insert_with(|| {
+                regions.push(Region { plant: grid[(row, col)], area: 0, perimeter: 0, sides: 0, shared: 0 });
+                regions.len() - 1
+            });
+            labels[(row, col)] = label;
+            regions[label].area += 1;
+        }
 
-fn edges(region: &HashSet<(usize, usize)>) -> usize {
-    let mut top = HashSet::new();
-    let mut bottom = HashSet::new();
-    let mut left = HashSet::new();
-    let mut right = HashSet::new();
-    for (row, col) in region {
-        let row = *row as i32;
-        let col = *col as i32;
-        for side in [Side::Bottom, Side::Top, Side::Left, Side::Right] {
-            let (dr, dc, dst) = match side {
-                Side::Top => (-1, 0, &mut top),
-                Side::Bottom => (1, 0, &mut bottom),
-                Side::Left => (0, -1, &mut left),
-                Side::Right => (0, 1, &mut right),
-            };
-            if (row == 0 && side == Side::Top)
-                || (col == 0 && side == Side::Left)
-                || !region.contains(&((row + dr) as usize, (col + dc) as usize))
-            {
-                dst.insert((row + dr, col + dc));
+        // Look at every 2x2 window, including the ones hanging off the edge.
+        // Each region sees a fence between two of its cells and a neighbour,
+        // and a corner wherever it has one or three of the four cells, or two
+        // that only touch diagonally.
+        let label = |row: usize, col: usize| -> Option<usize> {
+            (row > 0 && col > 0 && row <= rows && col <= cols).then(|| labels[(row - 1, col - 1)])
+        };
+        for (row, col) in (0..=rows).cartesian_product(0..=cols) {
+            let window = [label(row, col), label(row, col + 1), label(row + 1, col), label(row + 1, col + 1)];
+            // Between them, the windows' bottom pairs and right-hand pairs are
+            // every pair of neighbouring cells exactly once.
+            for (a, b) in [(window[2], window[3]), (window[1], window[3])] {
+                if a != b {
+                    a.into_iter().chain(b).for_each(|label| regions[label].perimeter += 1);
+                    if let (Some(a), Some(b)) = (a, b) {
+                        regions[a].shared += 1;
+                        regions[b].shared += 1;
+                    }
+                }
+            }
+            for label in window.iter().flatten().copied().unique() {
+                let inside = window.map(|cell| cell == Some(label));
+                regions[label].sides += match inside.iter().filter(|&&inside| inside).count() {
+                    1 | 3 => 1,
+                    2 if inside[0] == inside[3] => 2,
+                    _ => 0,
+                };
             }
         }
-    }
 
-    let mut count = 0;
+        Garden { labels, regions, connectivity }
+    }
 
-    let count_edges = move |mut set: HashSet<(i32, i32)>, horizontal| {
-        let mut count = 0;
-        while !set.is_empty() {
-            let origin = *set.iter().next().unwrap();
-            set.remove(&origin);
-            let (mut r_min, mut c_min) = origin;
-            let (mut r_max, mut c_max) = origin;
+    fn label(&self, row: usize, col: usize) -> Option<usize> {
+        self.labels.get(row, col).copied()
+    }
 
-            if horizontal {
-                c_min -= 1;
-            } else {
-                r_min -= 1;
+    // Every region's outline. Points are (x, y) on the lattice between cells,
+    // so cell (row, col) spans (col, row) to (col + 1, row + 1).
+    fn shapes(&self) -> Vec<Shape> {
+        // Fences go clockwise around their region, so the region is always on
+        // the right. Holes come out anticlockwise.
+        let mut fences: Vec<BTreeMap<(usize, usize), Vec<Heading>>> = vec![BTreeMap::new(); self.regions.len()];
+        for ((row, col), &label) in self.labels.indexed_iter() {
+            let sides = [
+                (row.checked_sub(1).and_then(|row| self.label(row, col)), (col, row), (1, 0)),
+                (self.label(row, col + 1), (col + 1, row), (0, 1)),
+                (self.label(row + 1, col), (col + 1, row + 1), (-1, 0)),
+                (col.checked_sub(1).and_then(|col| self.label(row, col)), (col, row + 1), (0, -1)),
+            ];
+            for (neighbour, start, heading) in sides {
+                if neighbour != Some(label) {
+                    fences[label].entry(start).or_default().push(heading);
+                }
             }
-            while set.contains(&(r_min, c_min)) {
-                set.remove(&(r_min, c_min));
-                if horizontal {
-                    c_min -= 1;
+        }
+
+        fences.into_iter().map(|mut fences| {
+            let mut shape = Shape { outer: vec![], holes: vec![] };
+            while let Some((&start, headings)) = fences.iter().next() {
+                let first = headings[0];
+                let (mut point, mut heading) = (start, first);
+                let mut ring = vec![];
+                loop {
+                    let headings = fences.get_mut(&point).unwrap();
+                    headings.retain(|&h| h != heading);
+                    if headings.is_empty() {
+                        fences.remove(&point);
+                    }
+                    point = ((point.0 as isize + heading.0) as usize, (point.1 as isize + heading.1) as usize);
+                    // Where two of the region's cells only touch at a corner,
+                    // turning right keeps them apart, and turning left joins them.
+                    let (dx, dy) = heading;
+                    let turns = match self.connectivity {
+                        Connectivity::Four => [(-dy, dx), heading, (dy, -dx)],
+                        Connectivity::Eight => [(dy, -dx), heading, (-dy, dx)],
+                    };
+                    let next = turns.into_iter().find(|h| {
+                        (point == start && *h == first) || fences.get(&point).is_some_and(|headings| headings.contains(h))
+                    }).expect("fences always join up");
+                    if next != heading {
+                        ring.push(point);
+                    }
+                    if point == start && next == first {
+                        break
+                    }
+                    heading = next;
+                }
+                // Start from the top-left corner, so outlines come out the same every time.
+                let first = ring.iter().position_min_by_key(|&&(x, y)| (y, x)).unwrap();
+                ring.rotate_left(first);
+                if area(&ring) > 0 {
+                    shape.outer = ring;
                 } else {
-                    r_min -= 1;
+                    shape.holes.push(ring);
                 }
             }
+            shape
+        }).collect()
+    }
 
-            if horizontal {
-                c_max += 1;
-            } else {
-                r_max += 1;
+    // The regions inside this one's holes, and any inside those. Unless the
+    // region counts diagonals, plants can squeeze out of a hole between two of
+    // its cells that only touch at a corner, just like the outlines.
+    fn enclosed(&self, label: usize) -> Vec<usize> {
+        let (rows, cols) = (self.labels.rows() as isize, self.labels.cols() as isize);
+        // Flood in from a ring of cells just outside the garden.
+        let mut outside = HashSet::new();
+        let mut frontier: Vec<(isize, isize)> = vec![(-1, -1)];
+        while let Some((row, col)) = frontier.pop() {
+            if row < -1 || col < -1 || row > rows || col > cols || outside.contains(&(row, col)) {
+                continue
             }
-            while set.contains(&(r_max, c_max)) {
-                set.remove(&(r_max, c_max));
-                if horizontal {
-                    c_max += 1;
-                } else {
-                    r_max += 1;
+            let inside_garden = row >= 0 && col >= 0 && row < rows && col < cols;
+            if inside_garden && self.labels[(row as usize, col as usize)] == label {
+                continue
+            }
+            outside.insert((row, col));
+            for (dr, dc) in (-1..=1).cartesian_product(-1..=1) {
+                if self.connectivity == Connectivity::Four || dr == 0 || dc == 0 {
+                    frontier.push((row + dr, col + dc));
                 }
             }
-            count += 1;
         }
-        count
-    };
+        self.labels.indexed_iter()
+            .filter(|&((row, col), &other)| other != label && !outside.contains(&(row as isize, col as isize)))
+            .map(|(_, &other)| other)
+            .unique()
+            .sorted()
+            .collect()
+    }
+
+    // Each region is one path, so the holes stay empty with the even-odd rule.
+    fn svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">\n",
+            self.labels.cols(), self.labels.rows());
+        for (region, shape) in self.regions.iter().zip(self.shapes()) {
+            let path = std::iter::once(&shape.outer).chain(shape.holes.iter())
+                .map(|ring| format!("M{}Z", ring.iter().map(|(x, y)| format!("{x},{y}")).join("L")))
+                .join(" ");
+            let hue = region.plant as u32 * 47 % 360;
+            svg += &format!(
+                "  <path d=\"{path}\" fill=\"hsl({hue},60%,60%)\" fill-rule=\"evenodd\" stroke=\"black\" stroke-width=\"0.05\"><title>{}</title></path>\n",
+                region.plant);
+        }
+        svg + "</svg>\n"
+    }
+
+    // GeoJSON has y going up, so rows go down from 0. It wants outlines
+    // anticlockwise and holes clockwise, the other way round from ours.
+    fn geojson(&self) -> String {
+        let features: Vec<_> = self.regions.iter().zip(self.shapes()).enumerate().map(|(label, (region, shape))| {
+            let rings: Vec<Vec<[i64; 2]>> = std::iter::once(&shape.outer).chain(shape.holes.iter()).map(|ring| {
+                ring[..1].iter().chain(ring[1..].iter().rev()).chain(&ring[..1]).map(|&(x, y)| [x as i64, -(y as i64)]).collect()
+            }).collect();
+            let ((left, top), (right, bottom)) = shape.bounding_box();
+            serde_json::json!({
+                "type": "Feature",
+                "bbox": [left as i64, -(bottom as i64), right as i64, -(top as i64)],
+                "geometry": { "type": "Polygon", "coordinates": rings },
+                "properties": {
+                    "label": label,
+                    "plant": region.plant.to_string(),
+                    "area": region.area,
+                    "perimeter": region.perimeter,
+                    "sides": region.sides,
+                    "corners": shape.corners(),
+                    "enclosed": if shape.holes.is_empty() { vec![] } else { self.enclosed(label) },
+                },
+            })
+        }).collect();
+        serde_json::json!({ "type": "FeatureCollection", "features": features }).to_string()
+    }
+}
+
+type Heading = (isize, isize);
+
+// A region's outline: its outer boundary and one ring for each hole, listing
+// just the corners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shape {
+    outer: Vec<(usize, usize)>,
+    holes: Vec<Vec<(usize, usize)>>,
+}
+
+impl Shape {
+    fn corners(&self) -> usize {
+        self.outer.len() + self.holes.iter().map(|hole| hole.len()).sum::<usize>()
+    }
 
-    count += count_edges(top, true);
-    count += count_edges(bottom, true);
-    count += count_edges(left, false);
-    count += count_edges(right, false);
+    // The top-left and bottom-right corners.
+    fn bounding_box(&self) -> ((usize, usize), (usize, usize)) {
+        let xs = self.outer.iter().map(|&(x, _)| x).minmax().into_option().unwrap();
+        let ys = self.outer.iter().map(|&(_, y)| y).minmax().into_option().unwrap();
+        ((xs.0, ys.0), (xs.1, ys.1))
+    }
+}
 
-    count
+// Twice the signed area, by the shoelace formula. With y going down, it's
+// positive for clockwise rings.
+fn area(ring: &[(usize, usize)]) -> isize {
+    ring.iter().zip(ring.iter().cycle().skip(1)).map(|(&(x1, y1), &(x2, y2))| {
+        x1 as isize * y2 as isize - x2 as isize * y1 as isize
+    }).sum()
 }
 
 fn parse(input: &str) -> Grid<char> {
@@ -268,6 +565,107 @@ XOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOXOX";
         assert_eq!(solve(&parse("C\nC")).1, 8)
     }
 
+    #[test]
+    fn labels() {
+        let garden = Garden::new(&parse(SAMPLE1));
+        assert_eq!(garden.labels, Grid::from_vec(vec![0, 0, 0, 0, 1, 1, 2, 3, 1, 1, 2, 2, 4, 4, 4, 2], 4));
+        assert_eq!(garden.regions[2], Region { plant: 'C', area: 4, perimeter: 10, sides: 8, shared: 7 });
+        let garden = Garden::new(&parse(SAMPLE2));
+        assert_eq!(garden.regions.len(), 5);
+        assert_eq!(garden.regions[0], Region { plant: 'O', area: 21, perimeter: 36, sides: 20, shared: 16 });
+    }
+
+    #[test]
+    fn corners() {
+        assert_eq!(solve(&parse("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE")).1, 236);
+        assert_eq!(solve(&parse("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA")).1, 368);
+    }
+
+    #[test]
+    fn outlines() {
+        let garden = Garden::new(&parse(SAMPLE2));
+        let shapes = garden.shapes();
+        assert_eq!(shapes[0].outer, [(0, 0), (5, 0), (5, 5), (0, 5)]);
+        assert_eq!(shapes[0].holes.len(), 4);
+        assert_eq!(shapes[0].holes[0], [(1, 1), (1, 2), (2, 2), (2, 1)]);
+        assert_eq!(shapes[0].bounding_box(), ((0, 0), (5, 5)));
+        assert_eq!(shapes[1], Shape { outer: vec![(1, 1), (2, 1), (2, 2), (1, 2)], holes: vec![] });
+        assert_eq!(garden.enclosed(0), [1, 2, 3, 4]);
+        assert!(garden.enclosed(1).is_empty());
+
+        // The two B squares touch at a corner, which makes one hole in A.
+        let garden = Garden::new(&parse("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA"));
+        let shapes = garden.shapes();
+        assert_eq!(shapes[0].holes.len(), 1);
+        assert_eq!(garden.enclosed(0), [1, 2]);
+
+        for input in [SAMPLE1, SAMPLE2, SAMPLE3, CHRISTMAS, "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA"] {
+            let garden = Garden::new(&parse(input));
+            for (region, shape) in garden.regions.iter().zip(garden.shapes()) {
+                assert_eq!(shape.corners(), region.sides);
+                let holes: isize = shape.holes.iter().map(|hole| area(hole)).sum();
+                assert_eq!(area(&shape.outer) + holes, 2 * region.area as isize);
+            }
+        }
+    }
+
+    #[test]
+    fn exports() {
+        let garden = Garden::new(&parse(SAMPLE2));
+        let svg = garden.svg();
+        assert_eq!(svg.matches("<path").count(), 5);
+        assert!(svg.contains("d=\"M0,0L5,0L5,5L0,5Z M1,1L1,2L2,2L2,1Z M"));
+        let json: serde_json::Value = serde_json::from_str(&garden.geojson()).unwrap();
+        let o = &json["features"][0];
+        assert_eq!(o["properties"]["enclosed"], serde_json::json!([1, 2, 3, 4]));
+        assert_eq!(o["geometry"]["coordinates"][0], serde_json::json!([[0, 0], [0, -5], [5, -5], [5, 0], [0, 0]]));
+        assert_eq!(o["geometry"]["coordinates"].as_array().unwrap().len(), 5);
+        assert_eq!(o["bbox"], serde_json::json!([0, -5, 5, 0]));
+    }
+
+    #[test]
+    fn policies() {
+        let grid = parse(SAMPLE1);
+        let perimeter = bill(&grid, &ByPerimeter);
+        assert_eq!(perimeter.0[0], Item { label: 0, plant: 'A', area: 4, fence: 10, unit_price: 1, discount: 0, price: 40 });
+        assert_eq!(bill(&parse(SAMPLE3), &BySides).total(), 1206);
+        let pricey = PerPlant { policy: ByPerimeter, prices: HashMap::from([('A', 2)]) };
+        assert_eq!(bill(&grid, &pricey).total(), 140 + 40);
+
+        let shared = bill(&grid, &SharedFences { policy: ByPerimeter, discount: 50 });
+        assert_eq!(shared.0.iter().map(|item| item.discount).collect::<Vec<_>>(), [8, 12, 14, 1, 6]);
+        assert_eq!(shared.total(), 140 - 41);
+        assert_eq!(shared.0[3].to_string(), "   3 D: area 1 x fence 4 x 1 - 1 = 3");
+        assert!(shared.to_string().ends_with("Total: 99"));
+        let stacked = Diagonal(SharedFences { policy: PerPlant { policy: BySides, prices: HashMap::from([('A', 3)]) }, discount: 100 });
+        assert_eq!(stacked.unit_price('A'), 3);
+        assert_eq!(stacked.shared_discount(), 100);
+
+        let grid = parse("AB\nBA");
+        assert_eq!(bill(&grid, &ByPerimeter).total(), 16);
+        assert_eq!(bill(&grid, &Diagonal(ByPerimeter)).total(), 32);
+        assert_eq!(bill(&grid, &Diagonal(BySides)).0.len(), 2);
+    }
+
+    #[test]
+    fn diagonal() {
+        // The Bs only touch at corners, but counting diagonals they ring the A.
+        let garden = Garden::with_connectivity(&parse("CBC\nBAB\nCBC"), Connectivity::Eight);
+        assert_eq!(garden.regions.len(), 6);
+        assert_eq!(garden.regions[1], Region { plant: 'B', area: 4, perimeter: 16, sides: 16, shared: 12 });
+        let shapes = garden.shapes();
+        assert_eq!(shapes[1].holes, [[(1, 1), (1, 2), (2, 2), (2, 1)]]);
+        assert_eq!(garden.enclosed(1), [3]);
+        for input in [SAMPLE1, SAMPLE3, CHRISTMAS, "AB\nBA", "CBC\nBAB\nCBC"] {
+            let garden = Garden::with_connectivity(&parse(input), Connectivity::Eight);
+            for (region, shape) in garden.regions.iter().zip(garden.shapes()) {
+                assert_eq!(shape.corners(), region.sides);
+                let holes: isize = shape.holes.iter().map(|hole| area(hole)).sum();
+                assert_eq!(area(&shape.outer) + holes, 2 * region.area as isize);
+            }
+        }
+    }
+
     #[test]
     fn christmas() {
         assert_eq!(solve(&parse(CHRISTMAS)), (426452, 307122))
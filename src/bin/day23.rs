@@ -188,7 +188,7 @@ td-yn";
     #[test]
     fn tiny() {
         let small = "aa-bb\nbb-cc\naa-cc\ncc-dd\nbb-dd";
-        part1_matrix(&small);
+        part1_matrix(small);
     }
 
     #[test]